@@ -0,0 +1,243 @@
+use crate::node::Node;
+use crate::permission::Permission;
+use crate::vm::gpsl::BUILTIN_FUNCTIONS;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// The permissions a single script function may need, found without running it.
+#[derive(Clone, Debug)]
+pub struct FunctionAudit {
+    pub name: String,
+    pub declared: Vec<Permission>,
+    pub calls: BTreeSet<String>,
+    pub unknown_calls: BTreeSet<String>,
+    pub direct: BTreeSet<Permission>,
+    pub transitive: BTreeSet<Permission>,
+    pub rejected: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditReport {
+    pub functions: Vec<FunctionAudit>,
+}
+
+/// The permissions known to hold at some point of a function body. `accept`
+/// stays `None` until a block names its grants, since until then they depend
+/// on the caller.
+#[derive(Clone)]
+struct Context {
+    accept: Option<Vec<Permission>>,
+    reject: Vec<Permission>,
+}
+
+impl Context {
+    /// Returns true if no caller can make `permission` available here.
+    fn rejects(&self, permission: &Permission) -> bool {
+        let rejected = self
+            .reject
+            .iter()
+            .any(|reject| reject.args.is_empty() && reject.name_implies(permission));
+        let not_accepted = match &self.accept {
+            Some(accept) => !accept.iter().any(|accept| accept.name_implies(permission)),
+            None => false,
+        };
+        rejected || not_accepted
+    }
+}
+
+struct Auditor<'a> {
+    functions: &'a HashMap<String, Box<Node>>,
+    external_permissions: &'a HashMap<String, Vec<Permission>>,
+}
+
+/// A call to a script function, checked once the permissions the callee
+/// needs through its own callees are known.
+struct ScriptCall {
+    callee: String,
+    context: Context,
+}
+
+impl<'a> Auditor<'a> {
+    fn walk(&self, node: &Node, context: &Context, audit: &mut FunctionAudit, script_calls: &mut Vec<ScriptCall>) {
+        match node {
+            Node::Block { stmts, permission: Some(permission) } => {
+                let mut inner = context.clone();
+                if let Node::Permission { accept, reject } = &**permission {
+                    for permission in accept {
+                        if context.rejects(permission) {
+                            audit.rejected.push(format!(
+                                "$(accept[{}]) widens the permissions of the enclosing block",
+                                permission
+                            ));
+                        }
+                    }
+                    if !accept.is_empty() {
+                        inner.accept = Some(accept.clone());
+                    }
+                    inner.reject.extend(reject.iter().cloned());
+                }
+
+                for stmt in stmts {
+                    self.walk(stmt, &inner, audit, script_calls);
+                }
+            }
+            Node::Call { name, args } => {
+                for arg in args {
+                    self.walk(arg, context, audit, script_calls);
+                }
+
+                audit.calls.insert(name.clone());
+                let required = if self.functions.contains_key(name) {
+                    script_calls.push(ScriptCall {
+                        callee: name.clone(),
+                        context: context.clone(),
+                    });
+                    vec![]
+                } else if name == "mint" {
+                    let permission = match args.first().map(|arg| &**arg) {
+                        Some(Node::Text { value }) => Permission::parse(value).ok(),
+                        _ => None,
+                    };
+                    audit.direct.extend(permission.clone());
+                    permission.into_iter().collect()
+                } else if BUILTIN_FUNCTIONS.contains(&name.as_str()) {
+                    vec![]
+                } else if let Some(required) = self.external_permissions.get(name) {
+                    audit.direct.extend(required.iter().cloned());
+                    required.clone()
+                } else {
+                    audit.unknown_calls.insert(name.clone());
+                    vec![]
+                };
+
+                for permission in required {
+                    if context.rejects(&permission) {
+                        audit.rejected.push(format!(
+                            "{}() needs {}, which the enclosing block does not grant",
+                            name, permission
+                        ));
+                    }
+                }
+            }
+            _ => {
+                for child in node.children() {
+                    self.walk(child, context, audit, script_calls);
+                }
+            }
+        }
+    }
+}
+
+/// Builds the call graph of `functions` and infers the permissions each
+/// function may need. `external_permissions` lists the permissions required
+/// by each known external function.
+pub fn audit(
+    functions: &HashMap<String, Box<Node>>,
+    external_permissions: &HashMap<String, Vec<Permission>>,
+) -> AuditReport {
+    let auditor = Auditor {
+        functions,
+        external_permissions,
+    };
+
+    let mut audits: HashMap<String, FunctionAudit> = HashMap::new();
+    let mut script_calls: HashMap<String, Vec<ScriptCall>> = HashMap::new();
+    for (name, function) in functions {
+        if !matches!(**function, Node::Function { .. }) {
+            continue;
+        }
+        let declared = match function.function_permission() {
+            Some(Node::Permission { accept, .. }) => accept.clone(),
+            _ => vec![],
+        };
+        let mut audit = FunctionAudit {
+            name: name.clone(),
+            declared,
+            calls: BTreeSet::new(),
+            unknown_calls: BTreeSet::new(),
+            direct: BTreeSet::new(),
+            transitive: BTreeSet::new(),
+            rejected: vec![],
+        };
+        let context = Context {
+            accept: None,
+            reject: vec![],
+        };
+        let mut calls = vec![];
+        auditor.walk(function, &context, &mut audit, &mut calls);
+        audit.transitive = audit.direct.clone();
+        audits.insert(name.clone(), audit);
+        script_calls.insert(name.clone(), calls);
+    }
+
+    // Propagate needs along the call graph until nothing changes, so that
+    // recursive functions terminate.
+    loop {
+        let mut changed = false;
+        let snapshot = audits.clone();
+        for audit in audits.values_mut() {
+            for callee in audit.calls.iter().filter_map(|call| snapshot.get(call)) {
+                for permission in callee.declared.iter().chain(&callee.transitive) {
+                    changed |= audit.transitive.insert(permission.clone());
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // A script function needs everything its callees need, so a call is
+    // rejected if the block around it rejects any of those.
+    let snapshot = audits.clone();
+    for (name, calls) in &script_calls {
+        let audit = audits.get_mut(name).unwrap();
+        for call in calls {
+            let callee = match snapshot.get(&call.callee) {
+                Some(callee) => callee,
+                None => continue,
+            };
+            let required: BTreeSet<&Permission> = callee.declared.iter().chain(&callee.transitive).collect();
+            for permission in required {
+                if call.context.rejects(permission) {
+                    audit.rejected.push(format!(
+                        "{}() needs {}, which the enclosing block does not grant",
+                        call.callee, permission
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut functions: Vec<FunctionAudit> = audits.into_values().collect();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    AuditReport { functions }
+}
+
+fn join<T: ToString>(items: impl IntoIterator<Item = T>) -> String {
+    let items: Vec<String> = items.into_iter().map(|item| item.to_string()).collect();
+    if items.is_empty() {
+        String::from("-")
+    } else {
+        items.join(", ")
+    }
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for audit in &self.functions {
+            writeln!(f, "fn {}", audit.name)?;
+            writeln!(f, "  declared:   {}", join(&audit.declared))?;
+            writeln!(f, "  calls:      {}", join(&audit.calls))?;
+            if !audit.unknown_calls.is_empty() {
+                writeln!(f, "  unknown:    {}", join(&audit.unknown_calls))?;
+            }
+            writeln!(f, "  direct:     {}", join(&audit.direct))?;
+            writeln!(f, "  transitive: {}", join(&audit.transitive))?;
+            for rejected in &audit.rejected {
+                writeln!(f, "  REJECTED:   {}", rejected)?;
+            }
+        }
+        Ok(())
+    }
+}
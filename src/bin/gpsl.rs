@@ -3,25 +3,107 @@ use gpsl::{
     source::Source,
     external_function::*,
     tokenizer::Tokenizer,
-    parser::Parser
+    parser::Parser,
+    audit::audit,
+    manifest::Manifest,
+    policy::Policy
 };
-use std::{fs, env, collections::HashMap};
+use std::{fs, env, path::Path, collections::HashMap};
+
+/// Command line options: `gpsl [audit] [-f] <script> [--policy <file>]`.
+struct Options {
+    audit: bool,
+    script: String,
+    policy: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut audit = false;
+    let mut script = None;
+    let mut policy = None;
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "audit" if script.is_none() && !audit => audit = true,
+            "-f" => {}
+            "--policy" => {
+                let path = args.next().ok_or_else(|| String::from("--policy needs a file."))?;
+                policy = Some(path.clone());
+            }
+            _ if script.is_none() => script = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    Ok(Options {
+        audit,
+        script: script.ok_or_else(|| String::from("Usage: gpsl [audit] [-f] <script> [--policy <file>]"))?,
+        policy,
+    })
+}
+
 fn main() {
     env::set_var("RUST_LOG", "info");
     env_logger::init();
 
     let args: Vec<String> = env::args().collect();
-    let mut source = Source::new(fs::read_to_string(&(args.last().unwrap())).expect("Cannot read file."));
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            println!("Error: {:?}", err);
+            return;
+        }
+    };
+    let mut source = match fs::read_to_string(&options.script) {
+        Ok(src) => Source::new(src),
+        Err(err) => {
+            println!("Error: {:?}", format!("{}: {}", options.script, err));
+            return;
+        }
+    };
 
     let mut tokenizer = Tokenizer::new();
-    tokenizer.tokenize(&mut source).unwrap();
+    if let Err(err) = tokenizer.tokenize(&mut source) {
+        println!("Error: {:?}", err);
+        return;
+    }
 
     let mut parser = Parser {
         tokenizer,
         local_vars: HashMap::new()
     };
 
-    let mut gpsl = GPSL::new(source, Some(parser.functions().unwrap()), vec![STD_FUNC]);
+    let functions = match parser.functions() {
+        Ok(functions) => functions,
+        Err(err) => {
+            println!("Error: {:?}", err);
+            return;
+        }
+    };
+
+    if options.audit {
+        print!("{}", audit(&functions, &std_func_permissions()));
+        return;
+    }
+
+    let mut gpsl = GPSL::new(source, Some(functions), vec![STD_FUNC]);
+    let manifest = Manifest::load_sidecar(Path::new(&options.script))
+        .and_then(|manifest| manifest.map_or(Ok(()), |manifest| gpsl.apply_manifest(&manifest)));
+    if let Err(err) = manifest {
+        println!("Error: {:?}", err);
+        return;
+    }
+
+    if let Some(policy) = &options.policy {
+        match Policy::load(Path::new(policy)) {
+            Ok(policy) => gpsl.policy = policy,
+            Err(err) => {
+                println!("Error: {:?}", err);
+                return;
+            }
+        }
+    }
+
     let res = gpsl.run("main".to_string(), vec![]);
     if let Err(err) = res {
         println!("Error: {:?}", err);
@@ -0,0 +1,194 @@
+use crate::node::{Node, Pattern};
+use crate::variable::Variable;
+use std::collections::HashMap;
+
+static WILDCARD: Pattern = Pattern::Wildcard;
+
+/// Checks the patterns of every `match` in `items` against the enum
+/// declarations among them, and that each `match` has an arm for every
+/// possible value.
+pub fn check(items: &HashMap<String, Box<Node>>) -> Result<(), String> {
+    let checker = Checker { items };
+    let mut names: Vec<&String> = items.keys().collect();
+    names.sort();
+    for name in names {
+        checker.check_node(name, &items[name])?;
+    }
+    Ok(())
+}
+
+struct Checker<'a> {
+    items: &'a HashMap<String, Box<Node>>,
+}
+
+impl<'a> Checker<'a> {
+    fn variants(&self, name: &str) -> Option<&'a Vec<(String, Vec<String>)>> {
+        match self.items.get(name).map(|node| &**node) {
+            Some(Node::Enum { variants, .. }) => Some(variants),
+            _ => None,
+        }
+    }
+
+    fn check_node(&self, function_name: &str, node: &Node) -> Result<(), String> {
+        if let Node::Match { arms, .. } = node {
+            let value_type = arms
+                .iter()
+                .find_map(|(pattern, _)| match pattern {
+                    Pattern::Variant { name, .. } => Some(name.clone()),
+                    Pattern::Literal(Variable::Bool { .. }) => Some(String::from("bool")),
+                    _ => None,
+                })
+                .unwrap_or_else(|| String::from("_"));
+
+            for (pattern, _) in arms {
+                self.check_pattern(pattern, &value_type)
+                    .map_err(|err| format!("{}: {}", function_name, err))?;
+            }
+
+            let rows = arms.iter().map(|(pattern, _)| vec![pattern]).collect();
+            if let Some(missing) = self.missing(rows, &[value_type]) {
+                return Err(format!(
+                    "{}: non-exhaustive match: `{}` not covered.",
+                    function_name, missing[0]
+                ));
+            }
+        }
+
+        for child in node.children() {
+            self.check_node(function_name, child)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that a variant pattern names a declared variant of `value_type`
+    /// with the right number of fields.
+    fn check_pattern(&self, pattern: &Pattern, value_type: &str) -> Result<(), String> {
+        if let Pattern::Variant { name, variant, fields } = pattern {
+            let variants = self.variants(name).ok_or_else(|| format!("unknown enum `{}`.", name))?;
+            if name != value_type {
+                return Err(format!("pattern `{}::{}` does not match type `{}`.", name, variant, value_type));
+            }
+            let (_, field_types) = variants
+                .iter()
+                .find(|(other, _)| other == variant)
+                .ok_or_else(|| format!("`{}` has no variant `{}`.", name, variant))?;
+            if fields.len() != field_types.len() {
+                return Err(format!(
+                    "{}::{}: expected {} fields, found {}.",
+                    name,
+                    variant,
+                    field_types.len(),
+                    fields.len()
+                ));
+            }
+            for (field, field_type) in fields.iter().zip(field_types) {
+                self.check_pattern(field, field_type)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns values of `types`, one per column, that no row matches, or
+    /// `None` if the rows cover every value.
+    fn missing(&self, rows: Vec<Vec<&'a Pattern>>, types: &[String]) -> Option<Vec<String>> {
+        let (value_type, rest_types) = match types.split_first() {
+            Some(split) => split,
+            None => return if rows.is_empty() { Some(vec![]) } else { None },
+        };
+
+        let constructors: Vec<(String, Vec<String>)> = if let Some(variants) = self.variants(value_type) {
+            variants
+                .iter()
+                .map(|(variant, fields)| (format!("{}::{}", value_type, variant), fields.clone()))
+                .collect()
+        } else if value_type == "bool" {
+            vec![(String::from("true"), vec![]), (String::from("false"), vec![])]
+        } else {
+            // Other types have too many values to list, so only arms that
+            // match anything cover them.
+            let rows = rows
+                .iter()
+                .filter(|row| row[0].is_irrefutable())
+                .map(|row| row[1..].to_vec())
+                .collect();
+            return self.missing(rows, rest_types).map(|rest| [vec![String::from("_")], rest].concat());
+        };
+
+        for (constructor, field_types) in constructors {
+            let mut specialized = vec![];
+            for row in &rows {
+                let fields: Vec<&Pattern> = match row[0] {
+                    pattern if pattern.is_irrefutable() => vec![&WILDCARD; field_types.len()],
+                    Pattern::Variant { name, variant, fields } if format!("{}::{}", name, variant) == constructor => {
+                        fields.iter().collect()
+                    }
+                    Pattern::Literal(Variable::Bool { value }) if value.to_string() == constructor => vec![],
+                    _ => continue,
+                };
+                specialized.push([fields, row[1..].to_vec()].concat());
+            }
+
+            let types = [field_types.clone(), rest_types.to_vec()].concat();
+            if let Some(missing) = self.missing(specialized, &types) {
+                let (fields, rest) = missing.split_at(field_types.len());
+                let value = if fields.is_empty() {
+                    constructor
+                } else {
+                    format!("{}({})", constructor, fields.join(", "))
+                };
+                return Some([vec![value], rest.to_vec()].concat());
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Parser;
+    use crate::source::Source;
+    use crate::tokenizer::Tokenizer;
+    use std::collections::HashMap;
+
+    fn parse(src: &str) -> Result<(), String> {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.tokenize(&mut Source::new(src.to_string()))?;
+        let mut parser = Parser {
+            tokenizer,
+            local_vars: HashMap::new(),
+        };
+        parser.functions().map(|_| ())
+    }
+
+    const ENUMS: &str = "enum Inner { A, B } enum Outer { X(Inner), Y }";
+
+    #[test]
+    fn reports_missing_nested_variant() {
+        let src = format!(
+            "{} fn f(o: Outer) {{ match (o) {{ Outer::X(Inner::A) => 1, Outer::Y => 2, }} }}",
+            ENUMS
+        );
+        assert_eq!(
+            parse(&src),
+            Err(String::from("f: non-exhaustive match: `Outer::X(Inner::B)` not covered."))
+        );
+    }
+
+    #[test]
+    fn accepts_exhaustive_nested_match() {
+        let src = format!(
+            "{} fn f(o: Outer) {{ match (o) {{ Outer::X(Inner::A) => 1, Outer::X(_) => 2, Outer::Y => 3, }} }}",
+            ENUMS
+        );
+        assert_eq!(parse(&src), Ok(()));
+    }
+
+    #[test]
+    fn rejects_pattern_of_another_enum() {
+        let src = format!("{} fn f(o: Outer) {{ match (o) {{ Outer::X(Outer::Y) => 1, _ => 2, }} }}", ENUMS);
+        assert_eq!(
+            parse(&src),
+            Err(String::from("f: pattern `Outer::Y` does not match type `Inner`."))
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{variable::Variable, permission::Permission};
+use std::collections::HashMap;
 
 #[derive(PartialEq)]
 pub enum ExternalFuncStatus {
@@ -8,6 +9,8 @@ pub enum ExternalFuncStatus {
     REJECTED,
 }
 
+/// The result of an external function. On `ERROR`, `value` may hold a
+/// `Variable::Text` describing the failure.
 pub struct ExternalFuncReturn {
     pub status: ExternalFuncStatus,
     pub value: Option<Variable>
@@ -18,11 +21,10 @@ pub const STD_FUNC: fn(String, Vec<Variable>, Vec<Permission>, Vec<Permission>)
     let name = name.as_str();
     match name {
         "println" => {
-            if accept.contains(&Permission::StdIo) && !reject.contains(&Permission::StdIo) {
-                match &args[0] {
-                    Variable::Text { value } => println!("{}", value),
-                    Variable::Number { value } => println!("{}", value),
-                    _ => {}
+            if Permission::std_io().is_granted(&accept, &reject) {
+                match args.iter().find(|arg| !matches!(arg, Variable::Capability { .. })) {
+                    Some(Variable::None {}) | None => {}
+                    Some(value) => println!("{}", value),
                 }
                 ExternalFuncReturn {
                     status: ExternalFuncStatus::SUCCESS,
@@ -36,11 +38,10 @@ pub const STD_FUNC: fn(String, Vec<Variable>, Vec<Permission>, Vec<Permission>)
             }
         }
         "print" => {
-            if accept.contains(&Permission::StdIo) && !reject.contains(&Permission::StdIo) {
-                match &args[0] {
-                    Variable::Text { value } => print!("{}", value),
-                    Variable::Number { value } => print!("{}", value),
-                    _ => {}
+            if Permission::std_io().is_granted(&accept, &reject) {
+                match args.iter().find(|arg| !matches!(arg, Variable::Capability { .. })) {
+                    Some(Variable::None {}) | None => {}
+                    Some(value) => print!("{}", value),
                 }
                 ExternalFuncReturn {
                     status: ExternalFuncStatus::SUCCESS,
@@ -53,6 +54,29 @@ pub const STD_FUNC: fn(String, Vec<Variable>, Vec<Permission>, Vec<Permission>)
                 }
             }
         }
+        "to_num" | "to_i64" | "to_f64" => {
+            let var_type = &name[3..];
+            let value = match args.as_slice() {
+                [Variable::Text { value }] => match var_type {
+                    "num" => value.trim().parse().map(|value| Variable::Number { value }).map_err(|_| ()),
+                    "i64" => value.trim().parse().map(|value| Variable::Int { value }).map_err(|_| ()),
+                    _ => value.trim().parse().map(|value| Variable::Float { value }).map_err(|_| ()),
+                }
+                .map_err(|_| format!("{}: cannot parse {:?}.", name, value)),
+                [value] => value.convert(var_type).map_err(|err| format!("{}: {}", name, err)),
+                _ => Err(format!("{}: expected 1 argument.", name)),
+            };
+            match value {
+                Ok(value) => ExternalFuncReturn {
+                    status: ExternalFuncStatus::SUCCESS,
+                    value: Some(value)
+                },
+                Err(err) => ExternalFuncReturn {
+                    status: ExternalFuncStatus::ERROR,
+                    value: Some(Variable::Text { value: err })
+                }
+            }
+        }
         _ => {
             ExternalFuncReturn {
                 status: ExternalFuncStatus::NOTFOUND,
@@ -61,3 +85,14 @@ pub const STD_FUNC: fn(String, Vec<Variable>, Vec<Permission>, Vec<Permission>)
         }
     }
 };
+
+/// Permissions required by each function in `STD_FUNC`.
+pub fn std_func_permissions() -> HashMap<String, Vec<Permission>> {
+    let mut permissions = HashMap::new();
+    permissions.insert(String::from("println"), vec![Permission::std_io()]);
+    permissions.insert(String::from("print"), vec![Permission::std_io()]);
+    for name in ["to_num", "to_i64", "to_f64"] {
+        permissions.insert(String::from(name), vec![]);
+    }
+    permissions
+}
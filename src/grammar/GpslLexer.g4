@@ -10,11 +10,19 @@ ADD: '+' ;
 SUB: '-' ;
 MUL: '*' ;
 DIV: '/' ;
+MOD: '%' ;
 CONJ: '&&' ;
+DISJ: '||' ;
 AND: '&' ;
+OR: '|' ;
+XOR: '^' ;
+TILDE: '~' ;
+SHL: '<<' ;
+SHR: '>>' ;
 EQ: '=' ;
 EQEQ: '==' ;
 NE: '!=' ;
+NOT: '!' ;
 BE: '>=' ;
 LE: '<=' ;
 BT: '>' ;
@@ -28,6 +36,12 @@ ADD_ASSIGNMENT: '+=' ;
 SUB_ASSIGNMENT: '-=' ;
 MUL_ASSIGNMENT: '*=' ;
 DIV_ASSIGNMENT: '/=' ;
+MOD_ASSIGNMENT: '%=' ;
+AND_ASSIGNMENT: '&=' ;
+OR_ASSIGNMENT: '|=' ;
+XOR_ASSIGNMENT: '^=' ;
+SHL_ASSIGNMENT: '<<=' ;
+SHR_ASSIGNMENT: '>>=' ;
 LPAREN: '(' ;
 RPAREN: ')' ;
 LCURL: '{' ;
@@ -35,16 +49,38 @@ RCURL: '}' ;
 LBRACKET: '[' ;
 RBRACKET: ']' ;
 ARROW: '->' ;
+FAT_ARROW: '=>' ;
+DOUBLE_COLON: '::' ;
 
 FN: 'fn' ;
+STRUCT: 'struct' ;
+ENUM: 'enum' ;
+MATCH: 'match' ;
 FOR: 'for' ;
 WHILE: 'while' ;
 IF: 'if' ;
 ELSE: 'else' ;
+BREAK: 'break' ;
+CONTINUE: 'continue' ;
 LET: 'let' ;
 RETURN: 'return' ;
+TRUE: 'true' ;
+FALSE: 'false' ;
 
-NUM: [1-9] [0-9]* ;
+NUM: [0-9]+ ;
+FLOAT: [0-9]+ DOT [0-9]+ ;
+
+TEXT
+    : QUOTE (ESCAPE | ~["\\])* QUOTE
+    | 'r' QUOTE ~["]* QUOTE
+    // A raw string may open with any number of '#' and ends at the first
+    // quote followed by the same number of them, which ANTLR cannot express.
+    | 'r' '#'+ QUOTE .*? QUOTE '#'+
+    ;
+fragment ESCAPE
+    : '\\' [ntr0\\"']
+    | '\\u{' [0-9a-fA-F]+ '}'
+    | '\\' '\r'? '\n' [ \t\r\n]*
+    ;
 
-TEXT: QUOTE [a-zA-Z0-9_-]* QUOTE ;
 IDENT: [a-zA-Z_]+ ;
\ No newline at end of file
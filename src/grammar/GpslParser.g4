@@ -1,8 +1,11 @@
 parser grammar GpslParser;
 options { tokenVocab = GpslLexer; }
 
-gpslFile: function* EOF ;
+gpslFile: item* EOF ;
+item: function | struct | enum ;
 
+struct: STRUCT IDENT LCURL (IDENT COLON IDENT COMMA?)* RCURL ;
+enum: ENUM IDENT LCURL (IDENT (LPAREN (IDENT COMMA?)* RPAREN)? COMMA?)* RCURL ;
 function: FN IDENT LPAREN (IDENT COLON IDENT COMMA?)* RPAREN (ARROW IDENT)? block ;
 
 program: stmt* ;
@@ -13,6 +16,9 @@ stmt: let
     | if
     | while
     | for
+    | break
+    | continue
+    | match
     | expr SEMICOLON
     ;
 
@@ -20,22 +26,46 @@ let: LET IDENT COLON IDENT SEMICOLON ;
 block: permission? LCURL stmt* RCURL ;
 return: RETURN expr? SEMICOLON ;
 if: IF LPAREN expr RPAREN stmt (ELSE stmt)? ;
-while: WHILE LPAREN expr RPAREN stmt ;
-for: FOR LPAREN expr? SEMICOLON expr? SEMICOLON expr? RPAREN stmt ;
+while: (IDENT COLON)? WHILE LPAREN expr RPAREN stmt ;
+for: (IDENT COLON)? FOR LPAREN expr? SEMICOLON expr? SEMICOLON expr? RPAREN stmt ;
+break: BREAK IDENT? SEMICOLON ;
+continue: CONTINUE IDENT? SEMICOLON ;
+match: MATCH LPAREN expr RPAREN LCURL arm* RCURL ;
+arm: pattern FAT_ARROW (stmt | expr (COMMA | SEMICOLON)?) COMMA? ;
+pattern: IDENT DOUBLE_COLON IDENT (LPAREN (pattern COMMA?)* RPAREN)?
+    | IDENT
+    | unary
+    ;
 
-permission: DOLLER LPAREN ( IDENT LBRACKET ( IDENT COMMA? )* RBRACKET COMMA? )* RPAREN ;
+permission: DOLLER LPAREN ( IDENT LBRACKET ( permission_item COMMA? )* RBRACKET COMMA? )* RPAREN ;
+permission_item: IDENT (DOT IDENT)* (LPAREN (TEXT COMMA?)* RPAREN)? ;
 
 expr: assign ;
-assign: equality (EQ assign)? ;
-equality: relational (EQEQ relational | NE relational | CONJ)* ;
-relational: add (LE add | LT add | BE add | BT add)* ;
-add: mul (ADD mul | SUB mul | SUB_ASSIGNMENT mul | ADD_ASSIGNMENT mul)* ;
-mul: unary (MUL unary | DIV unary | DIV_ASSIGNMENT unary | MUL_ASSIGNMENT unary)* ;
-
-primary: LPAREN expr RPAREN | function_call | TEXT | NUM ;
-function_call: IDENT LPAREN (unary COMMA?)* RPAREN ;
-
-unary: ADD primary
-    | SUB primary
-    | primary
+assign: logical_or ((EQ | ADD_ASSIGNMENT | SUB_ASSIGNMENT | MUL_ASSIGNMENT | DIV_ASSIGNMENT | MOD_ASSIGNMENT
+    | AND_ASSIGNMENT | OR_ASSIGNMENT | XOR_ASSIGNMENT | SHL_ASSIGNMENT | SHR_ASSIGNMENT) assign)? ;
+logical_or: logical_and (DISJ logical_and)* ;
+logical_and: equality (CONJ equality)* ;
+equality: relational (EQEQ relational | NE relational)* ;
+relational: bit_or (LE bit_or | LT bit_or | BE bit_or | BT bit_or)* ;
+bit_or: bit_xor (OR bit_xor)* ;
+bit_xor: bit_and (XOR bit_and)* ;
+bit_and: shift (AND shift)* ;
+shift: add (SHL add | SHR add)* ;
+add: mul (ADD mul | SUB mul)* ;
+mul: unary (MUL unary | DIV unary | MOD unary)* ;
+
+postfix: primary (LBRACKET expr RBRACKET | DOT IDENT)* ;
+primary: LPAREN expr RPAREN | list | map | struct_literal | enum_variant | function_call | template | TEXT | NUM | FLOAT | TRUE | FALSE ;
+enum_variant: IDENT DOUBLE_COLON IDENT (LPAREN (expr COMMA?)* RPAREN)? ;
+list: LBRACKET (expr COMMA?)* RBRACKET ;
+map: LCURL (expr COLON expr COMMA?)* RCURL ;
+struct_literal: IDENT LCURL (IDENT COLON expr COMMA?)* RCURL ;
+template: TEMPLATE (TEXT | LPAREN expr RPAREN)* ;
+function_call: IDENT LPAREN (expr COMMA?)* RPAREN ;
+
+unary: ADD postfix
+    | SUB postfix
+    | NOT unary
+    | TILDE unary
+    | postfix
     ;
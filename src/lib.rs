@@ -7,5 +7,9 @@ pub mod tokenizer;
 pub mod variable;
 pub mod external_function;
 pub mod permission;
+pub mod audit;
+pub mod manifest;
+pub mod policy;
+pub mod exhaustiveness;
 #[macro_use]
 extern crate log;
\ No newline at end of file
@@ -0,0 +1,159 @@
+use crate::node::Node;
+use crate::permission::Permission;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The maximum permissions of a script, read from a sidecar manifest such as
+/// `plugin.gpsl.toml`:
+///
+/// ```toml
+/// [permissions]
+/// accept = ["StdIo", 'FileRead("/data/*")']
+/// reject = ["Administrator"]
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manifest {
+    pub accept: Vec<Permission>,
+    pub reject: Vec<Permission>,
+}
+
+impl Manifest {
+    pub fn sidecar_path(script: &Path) -> PathBuf {
+        let mut path = script.as_os_str().to_owned();
+        path.push(".toml");
+        PathBuf::from(path)
+    }
+
+    /// Loads the sidecar manifest of `script`, if there is one.
+    pub fn load_sidecar(script: &Path) -> Result<Option<Manifest>, String> {
+        let path = Manifest::sidecar_path(script);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let src = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Manifest::parse(&src)
+            .map(Some)
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Parses the subset of TOML used by manifests: string arrays assigned to
+    /// `accept` and `reject`, optionally under a `[permissions]` table.
+    pub fn parse(src: &str) -> Result<Manifest, String> {
+        let mut manifest = Manifest::default();
+        let src = strip_comments(src);
+        let mut chars = src.chars().peekable();
+
+        loop {
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            let c = match chars.peek() {
+                Some(c) => *c,
+                None => return Ok(manifest),
+            };
+
+            if c == '[' {
+                let table: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                if table.trim_start_matches('[').trim() != "permissions" {
+                    return Err(format!("Unknown table: {}]", table));
+                }
+                continue;
+            }
+
+            let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+            let key = key.trim();
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            if chars.next() != Some('[') {
+                return Err(format!("{}: expected an array of strings.", key));
+            }
+
+            let mut values: Vec<Permission> = vec![];
+            loop {
+                match chars.next() {
+                    Some(quote) if quote == '"' || quote == '\'' => {
+                        let value = read_string(&mut chars, quote)?;
+                        values.push(Permission::parse(&value)?);
+                    }
+                    Some(']') => break,
+                    Some(c) if c == ',' || c.is_whitespace() => {}
+                    Some(c) => return Err(format!("{}: unexpected character `{}`.", key, c)),
+                    None => return Err(format!("{}: unterminated array.", key)),
+                }
+            }
+
+            match key {
+                "accept" => manifest.accept.extend(values),
+                "reject" => manifest.reject.extend(values),
+                _ => return Err(format!("Unknown key: {}", key)),
+            }
+        }
+    }
+
+    /// Checks that no `$(accept[...])` block in `functions` asks for more
+    /// than the manifest grants.
+    pub fn check(&self, functions: &HashMap<String, Box<Node>>) -> Result<(), String> {
+        let mut names: Vec<&String> = functions.keys().collect();
+        names.sort();
+        for name in names {
+            self.check_node(name, &functions[name])?;
+        }
+        Ok(())
+    }
+
+    fn check_node(&self, function_name: &str, node: &Node) -> Result<(), String> {
+        if let Node::Block { permission: Some(permission), .. } = node {
+            if let Node::Permission { accept, .. } = &**permission {
+                for permission in accept {
+                    if !permission.is_granted(&self.accept, &self.reject) {
+                        return Err(format!(
+                            "{}: {} is not granted by the manifest.",
+                            function_name, permission
+                        ));
+                    }
+                }
+            }
+        }
+
+        for child in node.children() {
+            self.check_node(function_name, child)?;
+        }
+        Ok(())
+    }
+}
+
+fn strip_comments(src: &str) -> String {
+    let mut stripped = String::new();
+    for line in src.lines() {
+        let mut quote: Option<char> = None;
+        for c in line.chars() {
+            match quote {
+                Some(q) if c == q => quote = None,
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None if c == '#' => break,
+                _ => {}
+            }
+            stripped.push(c);
+        }
+        stripped.push('\n');
+    }
+    stripped
+}
+
+fn read_string(chars: &mut impl Iterator<Item = char>, quote: char) -> Result<String, String> {
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('\\') if quote == '"' => match chars.next() {
+                Some(c) => value.push(c),
+                None => return Err(String::from("Unterminated string.")),
+            },
+            Some(c) if c == quote => return Ok(value),
+            Some(c) => value.push(c),
+            None => return Err(String::from("Unterminated string.")),
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use crate::permission::Permission;
+use crate::variable::Variable;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum NodeKind {
@@ -7,22 +8,63 @@ pub enum NodeKind {
     SUB,
     MUL,
     DIV,
+    MOD, // %
+    BITAND, // &
+    BITOR, // |
+    BITXOR, // ^
+    SHL, // <<
+    SHR, // >>
     EQ, // ==
     NE, // !=
     LT, // <
     LE, // <=
+    AND, // &&
+    OR, // ||
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// `_`
+    Wildcard,
+    /// A name that binds the matched value in the arm.
+    Binding(String),
+    Literal(Variable),
+    /// `Enum::Variant(p, ...)`, matching the payload with one pattern each.
+    Variant {
+        name: String,
+        variant: String,
+        fields: Vec<Pattern>,
+    },
+}
+
+impl Pattern {
+    /// Returns true if the pattern matches any value.
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self, Pattern::Wildcard | Pattern::Binding(_))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Node {
     Function {
         name: String,
-        args: HashMap<String, String>,
+        args: Vec<(String, String)>,
+        return_type: Option<String>,
         body: Vec<Box<Node>>
     },
+    /// A top-level `struct` declaration, kept alongside the functions.
+    Struct {
+        name: String,
+        fields: Vec<(String, String)>,
+    },
+    /// A top-level `enum` declaration with the payload types of each variant.
+    Enum {
+        name: String,
+        variants: Vec<(String, Vec<String>)>,
+    },
     Permission {
-        accept: Vec<String>,
-        reject: Vec<String>
+        accept: Vec<Permission>,
+        reject: Vec<Permission>
     },
     Operator {
         kind: NodeKind,
@@ -32,9 +74,50 @@ pub enum Node {
     Number {
         value: usize,
     },
+    Int {
+        value: i64,
+    },
+    Float {
+        value: f64,
+    },
+    Bool {
+        value: bool,
+    },
     Text {
         value: String,
     },
+    Interpolation {
+        parts: Vec<Box<Node>>,
+    },
+    Not {
+        lhs: Box<Node>,
+    },
+    BitNot {
+        lhs: Box<Node>,
+    },
+    List {
+        items: Vec<Box<Node>>,
+    },
+    Map {
+        entries: Vec<(Box<Node>, Box<Node>)>,
+    },
+    Index {
+        target: Box<Node>,
+        index: Box<Node>,
+    },
+    StructLiteral {
+        name: String,
+        fields: Vec<(String, Box<Node>)>,
+    },
+    Field {
+        target: Box<Node>,
+        name: String,
+    },
+    EnumVariant {
+        name: String,
+        variant: String,
+        args: Vec<Box<Node>>,
+    },
     Lvar {
         value: String,
     },
@@ -47,15 +130,30 @@ pub enum Node {
         else_stmt: Option<Box<Node>>,
     },
     While {
+        label: Option<String>,
         condition: Box<Node>,
         stmt: Box<Node>,
     },
     For {
+        label: Option<String>,
         init: Option<Box<Node>>,
         condition: Option<Box<Node>>,
         update: Option<Box<Node>>,
         stmt: Box<Node>,
     },
+    /// Leaves the innermost loop, or the enclosing loop named `label`.
+    Break {
+        label: Option<String>,
+    },
+    /// Skips to the next iteration of the innermost loop, or of the
+    /// enclosing loop named `label`.
+    Continue {
+        label: Option<String>,
+    },
+    Match {
+        value: Box<Node>,
+        arms: Vec<(Pattern, Box<Node>)>,
+    },
     Block {
         stmts: Vec<Box<Node>>,
         permission: Option<Box<Node>>
@@ -83,4 +181,54 @@ impl Node {
     pub fn new_lvar_node(value: String) -> Box<Node> {
         Box::new(Node::Lvar { value })
     }
+
+    /// Returns the direct child nodes, in evaluation order.
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Function { body, .. } => body.iter().map(|node| &**node).collect(),
+            Node::Operator { lhs, rhs, .. } => vec![lhs, rhs],
+            Node::Not { lhs } | Node::BitNot { lhs } => vec![lhs],
+            Node::Return { lhs } => vec![lhs],
+            Node::If { condition, stmt, else_stmt } => {
+                let mut children: Vec<&Node> = vec![condition, stmt];
+                children.extend(else_stmt.as_deref());
+                children
+            }
+            Node::While { condition, stmt, .. } => vec![condition, stmt],
+            Node::For { init, condition, update, stmt, .. } => {
+                let mut children: Vec<&Node> = vec![];
+                children.extend(init.as_deref());
+                children.extend(condition.as_deref());
+                children.extend(update.as_deref());
+                children.push(stmt);
+                children
+            }
+            Node::Block { stmts, .. } => stmts.iter().map(|node| &**node).collect(),
+            Node::Call { args, .. } => args.iter().map(|node| &**node).collect(),
+            Node::Interpolation { parts } => parts.iter().map(|node| &**node).collect(),
+            Node::List { items } => items.iter().map(|node| &**node).collect(),
+            Node::Map { entries } => entries.iter().flat_map(|(key, value)| [&**key, &**value]).collect(),
+            Node::Index { target, index } => vec![target, index],
+            Node::StructLiteral { fields, .. } => fields.iter().map(|(_, node)| &**node).collect(),
+            Node::Field { target, .. } => vec![target],
+            Node::EnumVariant { args, .. } => args.iter().map(|node| &**node).collect(),
+            Node::Match { value, arms } => {
+                let mut children: Vec<&Node> = vec![value];
+                children.extend(arms.iter().map(|(_, stmt)| &**stmt));
+                children
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Returns the permission clause leading a function body, which declares
+    /// the permissions the function needs from its callers.
+    pub fn function_permission(&self) -> Option<&Node> {
+        if let Node::Function { body, .. } = self {
+            if let Some(Node::Block { permission: Some(permission), .. }) = body.first().map(|node| &**node) {
+                return Some(permission);
+            }
+        }
+        None
+    }
 }
@@ -1,8 +1,26 @@
+use crate::exhaustiveness;
 use crate::node::*;
+use crate::permission::Permission;
 use crate::token::*;
 use crate::tokenizer::*;
+use crate::variable::Variable;
 use std::collections::HashMap;
 
+/// Compound assignment operators and the operator each one applies, so that
+/// `x op= rhs` means `x = x op (rhs)`.
+const COMPOUND_ASSIGNMENTS: &[(&str, NodeKind)] = &[
+    ("+=", NodeKind::ADD),
+    ("-=", NodeKind::SUB),
+    ("*=", NodeKind::MUL),
+    ("/=", NodeKind::DIV),
+    ("%=", NodeKind::MOD),
+    ("&=", NodeKind::BITAND),
+    ("|=", NodeKind::BITOR),
+    ("^=", NodeKind::BITXOR),
+    ("<<=", NodeKind::SHL),
+    (">>=", NodeKind::SHR),
+];
+
 #[derive(Clone)]
 pub struct Parser {
     pub tokenizer: Tokenizer,
@@ -10,20 +28,118 @@ pub struct Parser {
 }
 
 impl Parser {
+    /*
+        gpslFile: item* EOF ;
+        item: function | struct | enum ;
+    */
     pub fn functions(&mut self) -> Result<HashMap<String, Box<Node>>, String> {
         let mut nodes: HashMap<String, Box<Node>> = HashMap::new();
         loop {
             if self.tokenizer.current_token().kind != TokenKind::EOF {
-                let function = self.function()?;
-                if let Node::Function{name, .. } = *function.clone() {
-                    nodes.insert(name, function);
+                let item = match &*self.tokenizer.current_token().str {
+                    "struct" => self.structure()?,
+                    "enum" => self.enumeration()?,
+                    _ => self.function()?,
+                };
+                if let Node::Function { name, .. } | Node::Struct { name, .. } | Node::Enum { name, .. } = &*item {
+                    if nodes.contains_key(name) {
+                        return Err(format!("`{}` is defined more than once.", name));
+                    }
+                    nodes.insert(name.clone(), item.clone());
                 }
             } else {
+                for (name, node) in &nodes {
+                    Parser::check_loop_control(node, &[]).map_err(|err| format!("{}: {}", name, err))?;
+                }
+                exhaustiveness::check(&nodes)?;
                 return Ok(nodes);
             }
         }
     }
 
+    /// Checks that every `break` and `continue` is inside a loop and names
+    /// the label of an enclosing loop. `loops` holds the labels of the loops
+    /// around `node`.
+    fn check_loop_control(node: &Node, loops: &[Option<String>]) -> Result<(), String> {
+        match node {
+            Node::While { label, .. } | Node::For { label, .. } => {
+                if label.is_some() && loops.contains(label) {
+                    return Err(format!("loop label `{}` is already in use.", label.as_ref().unwrap()));
+                }
+                let loops = [loops, std::slice::from_ref(label)].concat();
+                for child in node.children() {
+                    Parser::check_loop_control(child, &loops)?;
+                }
+                return Ok(());
+            }
+            Node::Break { label } | Node::Continue { label } => {
+                let keyword = if matches!(node, Node::Break { .. }) { "break" } else { "continue" };
+                match label {
+                    _ if loops.is_empty() => return Err(format!("`{}` outside of a loop.", keyword)),
+                    Some(label) if !loops.iter().flatten().any(|other| other == label) => {
+                        return Err(format!("unknown loop label `{}`.", label))
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+
+        for child in node.children() {
+            Parser::check_loop_control(child, loops)?;
+        }
+        Ok(())
+    }
+
+    /*
+        struct: STRUCT IDENT LCURL (IDENT COLON IDENT COMMA?)* RCURL ;
+    */
+    pub fn structure(&mut self) -> Result<Box<Node>, String> {
+        self.tokenizer.expect(String::from("struct"))?;
+        let name = self.tokenizer.expect_ident()?;
+        self.tokenizer.expect(String::from("{"))?;
+        let mut fields: Vec<(String, String)> = vec![];
+        while !self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from("}")) {
+            let field = self.tokenizer.expect_ident()?;
+            self.tokenizer.expect(String::from(":"))?;
+            let field_type = self.tokenizer.expect_ident()?;
+            self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from(","));
+            if fields.iter().any(|(other, _)| *other == field) {
+                return Err(format!("{}: duplicate field `{}`.", name, field));
+            }
+            fields.push((field, field_type));
+        }
+
+        Ok(Box::new(Node::Struct { name, fields }))
+    }
+
+    /*
+        enum: ENUM IDENT LCURL (IDENT (LPAREN (IDENT COMMA?)* RPAREN)? COMMA?)* RCURL ;
+    */
+    pub fn enumeration(&mut self) -> Result<Box<Node>, String> {
+        self.tokenizer.expect(String::from("enum"))?;
+        let name = self.tokenizer.expect_ident()?;
+        self.tokenizer.expect(String::from("{"))?;
+        let mut variants: Vec<(String, Vec<String>)> = vec![];
+        while !self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from("}")) {
+            let variant = self.tokenizer.expect_ident()?;
+            let mut fields: Vec<String> = vec![];
+            if self.tokenizer.consume(String::from("(")) {
+                while !self.tokenizer.consume(String::from(")")) {
+                    fields.push(self.tokenizer.expect_ident()?);
+                    self.tokenizer.consume(String::from(","));
+                }
+            }
+            self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from(","));
+            if variants.iter().any(|(other, _)| *other == variant) {
+                return Err(format!("{}: duplicate variant `{}`.", name, variant));
+            }
+            variants.push((variant, fields));
+        }
+
+        Ok(Box::new(Node::Enum { name, variants }))
+    }
+
     /*
         function: FN IDENT LPAREN (IDENT COLON IDENT COMMA?)* RPAREN (ARROW IDENT)? block ;
     */
@@ -32,7 +148,7 @@ impl Parser {
             debug!("parsing function");
             let func_name = self.tokenizer.current_token().clone();
             self.tokenizer.expect_kind(TokenKind::IDENT)?;
-            let mut args = HashMap::new();
+            let mut args = vec![];
             self.tokenizer.expect(String::from("("))?;
             debug!("parsing args");
             while !self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from(")")) {
@@ -41,21 +157,27 @@ impl Parser {
                 self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from(":"));
                 let type_str = self.tokenizer.expect_ident()?;
                 self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from(","));
-                args.insert(name, type_str);
+                if args.iter().any(|(arg, _): &(String, String)| *arg == name) {
+                    return Err(format!("{}: duplicate argument `{}`.", func_name.str, name));
+                }
+                args.push((name, type_str));
             }
 
-            let mut nodes: Vec<Box<Node>> = vec![];
+            let return_type = if self.tokenizer.consume(String::from("->")) {
+                Some(self.tokenizer.expect_ident()?)
+            } else {
+                None
+            };
+
             debug!("parsing body node");
-            loop {
-                nodes.push(self.stmt()?);
-                debug!("body nodes parsed");
-                //self.tokenizer.expect(String::from("}"))?;
-                return Ok(Box::new(Node::Function {
-                    name: func_name.str,
-                    args,
-                    body: nodes
-                }));
-            }
+            let nodes: Vec<Box<Node>> = vec![self.stmt()?];
+            debug!("body nodes parsed");
+            Ok(Box::new(Node::Function {
+                name: func_name.str,
+                args,
+                return_type,
+                body: nodes
+            }))
         } else {
             println!("{:?}", self.tokenizer.current_token());
             Err(String::from("Unexpected token."))
@@ -83,6 +205,9 @@ impl Parser {
             | if
             | while
             | for
+            | break
+            | continue
+            | match
             | expr SEMICOLON
             ;
     */
@@ -103,6 +228,35 @@ impl Parser {
             }));
         }
 
+        if self
+            .tokenizer
+            .consume_kind_str(TokenKind::IDENT, String::from("match"))
+        {
+            self.tokenizer.expect(String::from("("))?;
+            let value = self.expr()?;
+            self.tokenizer.expect(String::from(")"))?;
+            self.tokenizer.expect(String::from("{"))?;
+            let mut arms: Vec<(Pattern, Box<Node>)> = vec![];
+            while !self.tokenizer.consume(String::from("}")) {
+                let pattern = self.pattern()?;
+                self.tokenizer.expect(String::from("=>"))?;
+                let token = self.tokenizer.current_token().clone();
+                let is_stmt = matches!(token.kind, TokenKind::RETURN | TokenKind::CONTROL)
+                    || (token.kind == TokenKind::RESERVED && (token.str == "{" || token.str == "$"))
+                    || (token.kind == TokenKind::IDENT && token.str == "match");
+                if is_stmt {
+                    arms.push((pattern, self.stmt()?));
+                } else {
+                    arms.push((pattern, self.expr()?));
+                    if !self.tokenizer.consume(String::from(",")) {
+                        self.tokenizer.consume(String::from(";"));
+                    }
+                }
+                self.tokenizer.consume(String::from(","));
+            }
+            return Ok(Box::new(Node::Match { value, arms }));
+        }
+
         debug!("parsing permission");
         let permission = if self.tokenizer.current_token().str == "$" {
             Some(self.permission()?)
@@ -133,6 +287,14 @@ impl Parser {
             return Ok(Box::new(node));
         }
 
+        let label = if self.at_loop_label() {
+            let label = self.tokenizer.expect_ident()?;
+            self.tokenizer.expect(String::from(":"))?;
+            Some(label)
+        } else {
+            None
+        };
+
         if self.tokenizer.current_token().kind == TokenKind::CONTROL {
             match &*self.tokenizer.current_token().str {
                 "if" => {
@@ -160,7 +322,7 @@ impl Parser {
                     let condition = self.expr()?;
                     self.tokenizer.expect(String::from(")"))?;
                     let stmt = self.stmt()?;
-                    return Ok(Box::new(Node::While { condition, stmt }));
+                    return Ok(Box::new(Node::While { label, condition, stmt }));
                 }
                 "for" => {
                     self.tokenizer.cursor += 1;
@@ -192,12 +354,28 @@ impl Parser {
                     let stmt = self.stmt()?;
 
                     return Ok(Box::new(Node::For {
+                        label,
                         init,
                         condition,
                         update,
                         stmt,
                     }));
                 }
+                "break" | "continue" => {
+                    let keyword = self.tokenizer.current_token().str.clone();
+                    self.tokenizer.cursor += 1;
+                    let label = if self.tokenizer.current_token().kind == TokenKind::IDENT {
+                        Some(self.tokenizer.expect_ident()?)
+                    } else {
+                        None
+                    };
+                    self.tokenizer.expect(String::from(";"))?;
+                    return Ok(Box::new(if keyword == "break" {
+                        Node::Break { label }
+                    } else {
+                        Node::Continue { label }
+                    }));
+                }
                 _ => {}
             }
         }
@@ -208,14 +386,57 @@ impl Parser {
     }
 
     /*
-        permission: DOLLER LPAREN ( IDENT LBRACKET ( IDENT COMMA? )* RBRACKET COMMA? )* RPAREN ;
+        match: MATCH LPAREN expr RPAREN LCURL arm* RCURL ;
+        arm: pattern FAT_ARROW (stmt | expr (COMMA | SEMICOLON)?) COMMA? ;
+        pattern: IDENT DOUBLE_COLON IDENT (LPAREN (pattern COMMA?)* RPAREN)?
+            | IDENT
+            | unary
+            ;
+    */
+    pub fn pattern(&mut self) -> Result<Pattern, String> {
+        let token = self.tokenizer.current_token().clone();
+        if token.kind == TokenKind::IDENT && token.str != "true" && token.str != "false" {
+            self.tokenizer.cursor += 1;
+            if !self.tokenizer.consume(String::from("::")) {
+                return Ok(if token.str == "_" {
+                    Pattern::Wildcard
+                } else {
+                    Pattern::Binding(token.str)
+                });
+            }
+
+            let variant = self.tokenizer.expect_ident()?;
+            let mut fields: Vec<Pattern> = vec![];
+            if self.tokenizer.consume(String::from("(")) {
+                while !self.tokenizer.consume(String::from(")")) {
+                    fields.push(self.pattern()?);
+                    self.tokenizer.consume(String::from(","));
+                }
+            }
+            return Ok(Pattern::Variant { name: token.str, variant, fields });
+        }
+
+        let value = match *self.unary()? {
+            Node::Number { value } => Variable::Number { value },
+            Node::Int { value } => Variable::Int { value },
+            Node::Float { value } => Variable::Float { value },
+            Node::Text { value } => Variable::Text { value },
+            Node::Bool { value } => Variable::Bool { value },
+            _ => return Err(String::from("Patterns must be literals, names or enum variants.")),
+        };
+        Ok(Pattern::Literal(value))
+    }
+
+    /*
+        permission: DOLLER LPAREN ( IDENT LBRACKET ( permission_item COMMA? )* RBRACKET COMMA? )* RPAREN ;
+        permission_item: IDENT (DOT IDENT)* (LPAREN (TEXT COMMA?)* RPAREN)? ;
     */
     pub fn permission(&mut self) -> Result<Box<Node>, String> {
         self.tokenizer.expect(String::from("$"))?;
         self.tokenizer.expect(String::from("("))?;
 
-        let mut accept: Vec<String> = vec![];
-        let mut reject: Vec<String> = vec![];
+        let mut accept: Vec<Permission> = vec![];
+        let mut reject: Vec<Permission> = vec![];
 
         while !self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from(")")) {
             let name = self.tokenizer.expect_ident()?;
@@ -224,7 +445,7 @@ impl Parser {
             }
             self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from("["));
             while !self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from("]")) {
-                let permission = self.tokenizer.expect_ident()?;
+                let permission = self.permission_item()?;
                 self.tokenizer.consume_kind_str(TokenKind::RESERVED, String::from(","));
 
                 if name == "accept" {
@@ -240,6 +461,27 @@ impl Parser {
         Ok(Box::new(Node::Permission { accept, reject }))
     }
 
+    pub fn permission_item(&mut self) -> Result<Permission, String> {
+        let mut name = self.tokenizer.expect_ident()?;
+        while self.tokenizer.consume(String::from(".")) {
+            name = format!("{}.{}", name, self.tokenizer.expect_ident()?);
+        }
+
+        let mut args: Vec<String> = vec![];
+        if self.tokenizer.consume(String::from("(")) {
+            while !self.tokenizer.consume(String::from(")")) {
+                if self.tokenizer.current_token().kind != TokenKind::TEXT {
+                    return Err(format!("{}: permission arguments must be strings.", name));
+                }
+                args.push(self.tokenizer.current_token().str.clone());
+                self.tokenizer.cursor += 1;
+                self.tokenizer.consume(String::from(","));
+            }
+        }
+
+        Ok(Permission::scoped(&name, args))
+    }
+
     /*
         expr: assign ;
     */
@@ -248,20 +490,58 @@ impl Parser {
     }
 
     /*
-        assign: equality (EQ assign)? ;
+        assign: logical_or ((EQ | ADD_ASSIGNMENT | SUB_ASSIGNMENT | MUL_ASSIGNMENT | DIV_ASSIGNMENT | MOD_ASSIGNMENT
+            | AND_ASSIGNMENT | OR_ASSIGNMENT | XOR_ASSIGNMENT | SHL_ASSIGNMENT | SHR_ASSIGNMENT) assign)? ;
     */
     pub fn assign(&mut self) -> Result<Box<Node>, String> {
-        let mut node = self.equality()?;
+        let node = self.logical_or()?;
 
         if self.tokenizer.consume(String::from("=")) {
-            node = Node::new_node(NodeKind::ASSIGN, node, self.assign()?);
+            return Ok(Node::new_node(NodeKind::ASSIGN, node, self.assign()?));
+        }
+
+        for (op, kind) in COMPOUND_ASSIGNMENTS {
+            if self.tokenizer.consume(String::from(*op)) {
+                let rhs = self.assign()?;
+                return Ok(Node::new_node(
+                    NodeKind::ASSIGN,
+                    node.clone(),
+                    Node::new_node(kind.clone(), node, rhs),
+                ));
+            }
+        }
+
+        Ok(node)
+    }
+
+    /*
+        logical_or: logical_and (DISJ logical_and)* ;
+    */
+    pub fn logical_or(&mut self) -> Result<Box<Node>, String> {
+        let mut node = self.logical_and()?;
+
+        while self.tokenizer.consume(String::from("||")) {
+            node = Node::new_node(NodeKind::OR, node, self.logical_and()?);
         }
 
         Ok(node)
     }
 
     /*
-        equality: relational (EQEQ relational | NE relational | CONJ)* ;
+        logical_and: equality (CONJ equality)* ;
+    */
+    pub fn logical_and(&mut self) -> Result<Box<Node>, String> {
+        let mut node = self.equality()?;
+
+        while self.tokenizer.consume(String::from("&&")) {
+            node = Node::new_node(NodeKind::AND, node, self.equality()?);
+        }
+
+        Ok(node)
+    }
+
+    /*
+        equality: relational (EQEQ relational | NE relational)* ;
     */
     pub fn equality(&mut self) -> Result<Box<Node>, String> {
         let mut node = self.relational()?;
@@ -278,20 +558,82 @@ impl Parser {
     }
 
     /*
-        relational: add (LE add | LT add | BE add | BT add)* ;
+        relational: bit_or (LE bit_or | LT bit_or | BE bit_or | BT bit_or)* ;
     */
     pub fn relational(&mut self) -> Result<Box<Node>, String> {
-        let mut node = self.add()?;
+        let mut node = self.bit_or()?;
 
         loop {
             if self.tokenizer.consume(String::from("<=")) {
-                node = Node::new_node(NodeKind::LE, node, self.add()?);
+                node = Node::new_node(NodeKind::LE, node, self.bit_or()?);
             } else if self.tokenizer.consume(String::from("<")) {
-                node = Node::new_node(NodeKind::LT, node, self.add()?);
+                node = Node::new_node(NodeKind::LT, node, self.bit_or()?);
             } else if self.tokenizer.consume(String::from(">=")) {
-                node = Node::new_node(NodeKind::LE, self.add()?, node);
+                node = Node::new_node(NodeKind::LE, self.bit_or()?, node);
             } else if self.tokenizer.consume(String::from(">")) {
-                node = Node::new_node(NodeKind::LT, self.add()?, node);
+                node = Node::new_node(NodeKind::LT, self.bit_or()?, node);
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    /*
+        bit_or: bit_xor (OR bit_xor)* ;
+    */
+    pub fn bit_or(&mut self) -> Result<Box<Node>, String> {
+        let mut node = self.bit_xor()?;
+
+        loop {
+            if self.tokenizer.consume(String::from("|")) {
+                node = Node::new_node(NodeKind::BITOR, node, self.bit_xor()?);
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    /*
+        bit_xor: bit_and (XOR bit_and)* ;
+    */
+    pub fn bit_xor(&mut self) -> Result<Box<Node>, String> {
+        let mut node = self.bit_and()?;
+
+        loop {
+            if self.tokenizer.consume(String::from("^")) {
+                node = Node::new_node(NodeKind::BITXOR, node, self.bit_and()?);
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    /*
+        bit_and: shift (AND shift)* ;
+    */
+    pub fn bit_and(&mut self) -> Result<Box<Node>, String> {
+        let mut node = self.shift()?;
+
+        loop {
+            if self.tokenizer.consume(String::from("&")) {
+                node = Node::new_node(NodeKind::BITAND, node, self.shift()?);
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    /*
+        shift: add (SHL add | SHR add)* ;
+    */
+    pub fn shift(&mut self) -> Result<Box<Node>, String> {
+        let mut node = self.add()?;
+
+        loop {
+            if self.tokenizer.consume(String::from("<<")) {
+                node = Node::new_node(NodeKind::SHL, node, self.add()?);
+            } else if self.tokenizer.consume(String::from(">>")) {
+                node = Node::new_node(NodeKind::SHR, node, self.add()?);
             } else {
                 return Ok(node);
             }
@@ -299,7 +641,7 @@ impl Parser {
     }
 
     /*
-        add: mul (ADD mul | SUB mul | SUB_ASSIGNMENT mul | ADD_ASSIGNMENT mul)* ;
+        add: mul (ADD mul | SUB mul)* ;
     */
     pub fn add(&mut self) -> Result<Box<Node>, String> {
         let mut node = self.mul()?;
@@ -309,18 +651,6 @@ impl Parser {
                 node = Node::new_node(NodeKind::ADD, node, self.mul()?);
             } else if self.tokenizer.consume(String::from("-")) {
                 node = Node::new_node(NodeKind::SUB, node, self.mul()?);
-            } else if self.tokenizer.consume(String::from("+=")) {
-                node = Node::new_node(
-                    NodeKind::ASSIGN,
-                    Box::new((*node).clone()),
-                    Node::new_node(NodeKind::ADD, node, self.mul()?),
-                );
-            } else if self.tokenizer.consume(String::from("-=")) {
-                node = Node::new_node(
-                    NodeKind::ASSIGN,
-                    Box::new((*node).clone()),
-                    Node::new_node(NodeKind::SUB, node, self.mul()?),
-                );
             } else {
                 return Ok(node);
             }
@@ -328,7 +658,7 @@ impl Parser {
     }
 
     /*
-        mul: unary (MUL unary | DIV unary | DIV_ASSIGNMENT unary | MUL_ASSIGNMENT unary)* ;
+        mul: unary (MUL unary | DIV unary | MOD unary)* ;
     */
     pub fn mul(&mut self) -> Result<Box<Node>, String> {
         let mut node = self.unary()?;
@@ -337,18 +667,8 @@ impl Parser {
                 node = Node::new_node(NodeKind::MUL, node, self.unary()?);
             } else if self.tokenizer.consume(String::from("/")) {
                 node = Node::new_node(NodeKind::DIV, node, self.unary()?);
-            } else if self.tokenizer.consume(String::from("*=")) {
-                node = Node::new_node(
-                    NodeKind::ASSIGN,
-                    Box::new((*node).clone()),
-                    Node::new_node(NodeKind::MUL, node, self.unary()?),
-                );
-            } else if self.tokenizer.consume(String::from("/=")) {
-                node = Node::new_node(
-                    NodeKind::ASSIGN,
-                    Box::new((*node).clone()),
-                    Node::new_node(NodeKind::DIV, node, self.unary()?),
-                );
+            } else if self.tokenizer.consume(String::from("%")) {
+                node = Node::new_node(NodeKind::MOD, node, self.unary()?);
             } else {
                 return Ok(node);
             }
@@ -356,7 +676,61 @@ impl Parser {
     }
 
     /*
-        primary: LPAREN expr RPAREN | function_call | TEXT | NUM ;
+        postfix: primary (LBRACKET expr RBRACKET | DOT IDENT)* ;
+    */
+    pub fn postfix(&mut self) -> Result<Box<Node>, String> {
+        let mut node = self.primary()?;
+        loop {
+            if self.tokenizer.consume(String::from("[")) {
+                let index = self.expr()?;
+                self.tokenizer.expect(String::from("]"))?;
+                node = Box::new(Node::Index { target: node, index });
+            } else if self.tokenizer.consume(String::from(".")) {
+                let name = self.tokenizer.expect_ident()?;
+                node = Box::new(Node::Field { target: node, name });
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    /// Returns true if the tokens ahead are a loop label `IDENT :` followed
+    /// by `while` or `for`.
+    fn at_loop_label(&self) -> bool {
+        match &self.tokenizer.tokens[self.tokenizer.cursor..] {
+            [label, colon, control, ..] => {
+                label.kind == TokenKind::IDENT
+                    && colon.kind == TokenKind::RESERVED
+                    && colon.str == ":"
+                    && control.kind == TokenKind::CONTROL
+                    && (control.str == "while" || control.str == "for")
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the tokens after a name start a struct construction
+    /// such as `Point { x: 1 }` rather than anything else followed by `{`.
+    fn at_struct_literal(&self) -> bool {
+        let is = |token: &Token, kind: TokenKind, str: &str| token.kind == kind && token.str == str;
+        match &self.tokenizer.tokens[self.tokenizer.cursor..] {
+            [open, close, ..] if is(open, TokenKind::RESERVED, "{") && is(close, TokenKind::RESERVED, "}") => true,
+            [open, field, colon, ..] => {
+                is(open, TokenKind::RESERVED, "{")
+                    && field.kind == TokenKind::IDENT
+                    && is(colon, TokenKind::RESERVED, ":")
+            }
+            _ => false,
+        }
+    }
+
+    /*
+        primary: LPAREN expr RPAREN | list | map | struct_literal | enum_variant | function_call | template | TEXT | NUM | FLOAT | TRUE | FALSE ;
+        enum_variant: IDENT DOUBLE_COLON IDENT (LPAREN (expr COMMA?)* RPAREN)? ;
+        list: LBRACKET (expr COMMA?)* RBRACKET ;
+        map: LCURL (expr COLON expr COMMA?)* RCURL ;
+        struct_literal: IDENT LCURL (IDENT COLON expr COMMA?)* RCURL ;
+        template: TEMPLATE (TEXT | LPAREN expr RPAREN)* ;
     */
     pub fn primary(&mut self) -> Result<Box<Node>, String> {
         if self.tokenizer.consume(String::from("(")) {
@@ -365,24 +739,89 @@ impl Parser {
             return Ok(node);
         }
 
+        // `[` only starts a list here; permission lists are parsed by
+        // `permission`.
+        if self.tokenizer.consume(String::from("[")) {
+            let mut items: Vec<Box<Node>> = vec![];
+            while !self.tokenizer.consume(String::from("]")) {
+                items.push(self.expr()?);
+                self.tokenizer.consume(String::from(","));
+            }
+            return Ok(Box::new(Node::List { items }));
+        }
+
+        // Likewise `{` only starts a map here, since `stmt` parses blocks
+        // before expressions.
+        if self.tokenizer.consume(String::from("{")) {
+            let mut entries: Vec<(Box<Node>, Box<Node>)> = vec![];
+            while !self.tokenizer.consume(String::from("}")) {
+                let key = self.expr()?;
+                self.tokenizer.expect(String::from(":"))?;
+                entries.push((key, self.expr()?));
+                self.tokenizer.consume(String::from(","));
+            }
+            return Ok(Box::new(Node::Map { entries }));
+        }
+
+        if self.tokenizer.consume_kind_str(TokenKind::IDENT, String::from("true")) {
+            return Ok(Box::new(Node::Bool { value: true }));
+        }
+        if self.tokenizer.consume_kind_str(TokenKind::IDENT, String::from("false")) {
+            return Ok(Box::new(Node::Bool { value: false }));
+        }
+
         if self.tokenizer.current_token().kind == TokenKind::IDENT {
             let node = self.tokenizer.expect_ident()?;
+            if self.tokenizer.consume(String::from("::")) {
+                let variant = self.tokenizer.expect_ident()?;
+                let mut args: Vec<Box<Node>> = vec![];
+                if self.tokenizer.consume(String::from("(")) {
+                    while !self.tokenizer.consume(String::from(")")) {
+                        args.push(self.expr()?);
+                        self.tokenizer.consume(String::from(","));
+                    }
+                }
+                return Ok(Box::new(Node::EnumVariant { name: node, variant, args }));
+            }
             if self.tokenizer.consume(String::from("(")) {
                 let mut args: Vec<Box<Node>> = vec![];
-                while self.tokenizer.current_token().str != ")" {
-                    args.push(self.unary()?);
+                while !self.tokenizer.consume(String::from(")")) {
+                    args.push(self.expr()?);
                     self.tokenizer.consume(String::from(","));
                 }
 
-                self.tokenizer.expect(String::from(")"))?;
                 return Ok(Box::new(Node::Call {
                     name: node.clone(),
-                    args: args,
+                    args,
                 }))
             }
+            if self.at_struct_literal() {
+                self.tokenizer.expect(String::from("{"))?;
+                let mut fields: Vec<(String, Box<Node>)> = vec![];
+                while !self.tokenizer.consume(String::from("}")) {
+                    let field = self.tokenizer.expect_ident()?;
+                    self.tokenizer.expect(String::from(":"))?;
+                    if fields.iter().any(|(other, _)| *other == field) {
+                        return Err(format!("{}: duplicate field `{}`.", node, field));
+                    }
+                    fields.push((field, self.expr()?));
+                    self.tokenizer.consume(String::from(","));
+                }
+                return Ok(Box::new(Node::StructLiteral { name: node, fields }));
+            }
             return Ok(Node::new_lvar_node(node.clone()));
         }
 
+        if self.tokenizer.current_token().kind == TokenKind::TEMPLATE {
+            let count = self.tokenizer.current_token().num;
+            self.tokenizer.cursor += 1;
+            let mut parts: Vec<Box<Node>> = vec![];
+            for _ in 0..count {
+                parts.push(self.primary()?);
+            }
+            return Ok(Box::new(Node::Interpolation { parts }));
+        }
+
         if self.tokenizer.current_token().kind == TokenKind::TEXT {
             let text = self.tokenizer.current_token().str.clone();
             self.tokenizer.consume_kind(TokenKind::TEXT);
@@ -391,26 +830,45 @@ impl Parser {
             }));
         }
 
+        if self.tokenizer.current_token().kind == TokenKind::FLOAT {
+            return Ok(Box::new(Node::Float {
+                value: self.tokenizer.expect_float()?,
+            }));
+        }
+
         return Ok(Node::new_num_node(self.tokenizer.expect_number()?));
     }
 
     /*
-        unary: ADD primary
-            | SUB primary
-            | primary
+        unary: ADD postfix
+            | SUB postfix
+            | NOT unary
+            | TILDE unary
+            | postfix
             ;
     */
     pub fn unary(&mut self) -> Result<Box<Node>, String> {
         if self.tokenizer.consume(String::from("+")) {
-            return Ok(self.primary()?);
+            return Ok(self.postfix()?);
+        }
+        if self.tokenizer.consume(String::from("!")) {
+            return Ok(Box::new(Node::Not { lhs: self.unary()? }));
+        }
+        if self.tokenizer.consume(String::from("~")) {
+            return Ok(Box::new(Node::BitNot { lhs: self.unary()? }));
         }
         if self.tokenizer.consume(String::from("-")) {
-            return Ok(Node::new_node(
-                NodeKind::SUB,
-                Node::new_num_node(0),
-                self.primary()?,
-            ));
+            let node = self.postfix()?;
+            return match *node {
+                Node::Number { value } => Ok(Box::new(Node::Int {
+                    value: 0i64
+                        .checked_sub_unsigned(value as u64)
+                        .ok_or(format!("Number literal out of range: -{}", value))?,
+                })),
+                Node::Float { value } => Ok(Box::new(Node::Float { value: -value })),
+                _ => Ok(Node::new_node(NodeKind::SUB, Box::new(Node::Int { value: 0 }), node)),
+            };
         }
-        return Ok(self.primary()?);
+        return Ok(self.postfix()?);
     }
 }
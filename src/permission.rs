@@ -1,16 +1,278 @@
+use crate::parser::Parser;
+use crate::source::Source;
+use crate::token::TokenKind;
+use crate::tokenizer::Tokenizer;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub enum Permission {
-    Administrator,
-    StdIo
+/// A named permission. Names form a hierarchy separated by dots, so holding
+/// `Fs` implies holding `Fs.Read`. Arguments narrow the permission to a
+/// scope such as `FileRead("/data/*")`, where `*` matches any text.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Permission {
+    pub name: String,
+    pub args: Vec<String>,
 }
 
 impl Permission {
-    pub fn from_string(permission: &str) -> Self {
-        match permission {
-            "Administrator" => Self::Administrator,
-            "StdIo" => Self::StdIo,
-            _ => panic!("Permission not found.")
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), args: vec![] }
+    }
+
+    pub fn scoped(name: &str, args: Vec<String>) -> Self {
+        Self { name: name.to_string(), args }
+    }
+
+    /// Parses a permission written as in a `$(accept[...])` list.
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.tokenize(&mut Source::new(src.to_string()))?;
+        let mut parser = Parser {
+            tokenizer,
+            local_vars: HashMap::new(),
+        };
+
+        let permission = parser.permission_item()?;
+        if parser.tokenizer.current_token().kind != TokenKind::EOF {
+            return Err(format!("Invalid permission: {}", src));
+        }
+        Ok(permission)
+    }
+
+    pub fn administrator() -> Self {
+        Self::new("Administrator")
+    }
+
+    pub fn std_io() -> Self {
+        Self::new("StdIo")
+    }
+
+    pub fn parent(&self) -> Option<Permission> {
+        self.name.rfind('.').map(|pos| Self::new(&self.name[..pos]))
+    }
+
+    /// Returns true if `self` names `other` or one of its ancestors,
+    /// ignoring scope arguments.
+    pub fn name_implies(&self, other: &Permission) -> bool {
+        other.name == self.name
+            || (other.name.starts_with(&self.name) && other.name[self.name.len()..].starts_with('.'))
+    }
+
+    /// Returns true if holding `self` grants `other`. An unscoped permission
+    /// covers every scope; a scoped one only covers matching arguments.
+    ///
+    /// Arguments are matched as text, so an argument with a `.` or `..` path
+    /// segment is never covered by a scope: `FileRead("/data/*")` does not
+    /// grant `FileRead("/data/../etc/passwd")`. Callers checking a path must
+    /// still canonicalize it first, since symbolic links are not resolved.
+    pub fn implies(&self, other: &Permission) -> bool {
+        if !self.name_implies(other) {
+            return false;
+        }
+
+        self.args.is_empty()
+            || (self.args.len() == other.args.len()
+                && self
+                    .args
+                    .iter()
+                    .zip(&other.args)
+                    .all(|(pattern, arg)| !has_relative_segment(arg) && glob_match(pattern, arg)))
+    }
+
+    pub fn is_granted(&self, accept: &[Permission], reject: &[Permission]) -> bool {
+        accept.iter().any(|permission| permission.implies(self))
+            && !reject.iter().any(|permission| permission.implies(self))
+    }
+
+    /// Returns true if at least some scope of `self` is granted. External
+    /// functions still check the concrete resource they access.
+    pub fn is_partially_granted(&self, accept: &[Permission], reject: &[Permission]) -> bool {
+        accept.iter().any(|permission| permission.name_implies(self))
+            && !reject
+                .iter()
+                .any(|permission| permission.args.is_empty() && permission.name_implies(self))
+    }
+}
+
+/// An answer to `GPSL::prompt`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptDecision {
+    AllowOnce,
+    AllowAlways,
+    Deny,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PermissionDecision {
+    Granted,
+    Rejected,
+}
+
+/// A permission check made when a script calls an external function.
+#[derive(Clone, Debug)]
+pub struct PermissionEvent {
+    /// The checked permission, or `None` when the external function is not
+    /// listed in `GPSL::external_permissions` and rejected the call itself.
+    pub permission: Option<Permission>,
+    pub function: String,
+    /// Script functions on the call stack, outermost first.
+    pub caller_chain: Vec<String>,
+    pub decision: PermissionDecision,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.args.is_empty() {
+            let args: Vec<String> = self.args.iter().map(|arg| format!("{:?}", arg)).collect();
+            write!(f, "({})", args.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if `arg` has a `.` or `..` segment when read as a path.
+fn has_relative_segment(arg: &str) -> bool {
+    arg.split(['/', '\\']).any(|segment| segment == "." || segment == "..")
+}
+
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
         }
     }
-}
\ No newline at end of file
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// The set of permission names a host accepts in scripts.
+#[derive(Clone, Debug)]
+pub struct PermissionRegistry {
+    names: BTreeSet<String>,
+}
+
+impl Default for PermissionRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PermissionRegistry {
+    pub fn new() -> Self {
+        let mut names = BTreeSet::new();
+        names.insert(Permission::administrator().name);
+        names.insert(Permission::std_io().name);
+        Self { names }
+    }
+
+    /// Registers a permission. A dotted name such as `Fs.Read` requires its
+    /// parent `Fs` to be registered first.
+    pub fn register(&mut self, name: &str) -> Result<Permission, String> {
+        let valid = name
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_'));
+        if !valid {
+            return Err(format!("Invalid permission name: {}", name));
+        }
+
+        let permission = Permission::new(name);
+        if let Some(parent) = permission.parent() {
+            if !self.names.contains(&parent.name) {
+                return Err(format!("{}: parent permission {} is not registered.", name, parent));
+            }
+        }
+
+        self.names.insert(permission.name.clone());
+        Ok(permission)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Permission, String> {
+        if self.names.contains(name) {
+            Ok(Permission::new(name))
+        } else {
+            Err(format!("Permission not found: {}", name))
+        }
+    }
+
+    pub fn check(&self, permission: &Permission) -> Result<(), String> {
+        self.get(&permission.name).map(|_| ())
+    }
+
+    pub fn permissions(&self) -> Vec<Permission> {
+        self.names.iter().map(|name| Permission::new(name)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scoped(name: &str, arg: &str) -> Permission {
+        Permission::scoped(name, vec![arg.to_string()])
+    }
+
+    #[test]
+    fn glob_match_backtracks_over_stars() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("/data/*", "/data/a/b.txt"));
+        assert!(glob_match("a*bc", "abcbc"));
+        assert!(glob_match("*ab", "aab"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b", "acbx"));
+        assert!(!glob_match("/data/*", "/etc/data/x"));
+    }
+
+    #[test]
+    fn parent_scope_implies_narrower_child_scope() {
+        let parent = scoped("FileRead", "/data/*");
+        assert!(parent.implies(&scoped("FileRead", "/data/x.txt")));
+        assert!(parent.implies(&scoped("FileRead", "/data/*")));
+        assert!(!parent.implies(&scoped("FileRead", "/etc/passwd")));
+        assert!(!scoped("FileRead", "/data/x.txt").implies(&parent));
+        assert!(!parent.implies(&Permission::new("FileRead")));
+        assert!(Permission::new("FileRead").implies(&parent));
+    }
+
+    #[test]
+    fn scopes_do_not_cover_path_traversal() {
+        let parent = scoped("FileRead", "/data/*");
+        assert!(!parent.implies(&scoped("FileRead", "/data/../etc/passwd")));
+        assert!(!parent.implies(&scoped("FileRead", "/data/./x")));
+        assert!(!parent.implies(&scoped("FileRead", "/data/..\\secret")));
+        assert!(parent.implies(&scoped("FileRead", "/data/x..y/.hidden")));
+        assert!(Permission::new("FileRead").implies(&scoped("FileRead", "/data/../etc/passwd")));
+    }
+
+    #[test]
+    fn names_imply_dotted_children_only() {
+        let fs = Permission::new("Fs");
+        assert!(fs.implies(&Permission::new("Fs.Read")));
+        assert!(fs.implies(&scoped("Fs.Read", "/tmp/*")));
+        assert!(!fs.implies(&Permission::new("FsExtra")));
+        assert!(!Permission::new("Fs.Read").implies(&fs));
+    }
+
+    #[test]
+    fn reject_overrides_accept() {
+        let accept = vec![Permission::new("Fs")];
+        let reject = vec![scoped("Fs.Read", "/secret/*")];
+        assert!(scoped("Fs.Read", "/data/x").is_granted(&accept, &reject));
+        assert!(!scoped("Fs.Read", "/secret/key").is_granted(&accept, &reject));
+    }
+}
@@ -0,0 +1,103 @@
+use crate::permission::{glob_match, Permission};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolicyEffect {
+    /// `deny <permission> in <pattern>`: denied while any function on the
+    /// call stack matches the pattern.
+    Deny,
+    /// `allow <permission> only in <pattern>`: denied unless some function
+    /// on the call stack matches the pattern.
+    AllowOnly,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyRule {
+    pub effect: PolicyEffect,
+    pub permission: Permission,
+    /// A function name pattern where `*` matches any text.
+    pub pattern: String,
+}
+
+impl PolicyRule {
+    pub fn denies(&self, permission: &Permission, caller_chain: &[String]) -> bool {
+        if !self.permission.name_implies(permission) {
+            return false;
+        }
+
+        let inside = caller_chain.iter().any(|function| glob_match(&self.pattern, function));
+        match self.effect {
+            PolicyEffect::Deny => inside,
+            PolicyEffect::AllowOnly => !inside,
+        }
+    }
+}
+
+impl fmt::Display for PolicyRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.effect {
+            PolicyEffect::Deny => write!(f, "deny {} in {}", self.permission, self.pattern),
+            PolicyEffect::AllowOnly => write!(f, "allow {} only in {}", self.permission, self.pattern),
+        }
+    }
+}
+
+/// Host-wide rules evaluated together with the block permissions. Rules can
+/// only deny, so the decision does not depend on the order they are listed in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Policy {
+    pub rules: Vec<PolicyRule>,
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Policy, String> {
+        let src = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Policy::parse(&src).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Parses one rule per line. Lines starting with `#` are comments.
+    pub fn parse(src: &str) -> Result<Policy, String> {
+        let mut rules = vec![];
+        for (number, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || format!("line {}: invalid rule: {}", number + 1, line);
+            let (head, pattern) = line.rsplit_once(" in ").ok_or_else(invalid)?;
+            let (effect, permission) = if let Some(permission) = head.strip_prefix("deny ") {
+                (PolicyEffect::Deny, permission)
+            } else if let Some(permission) = head.strip_prefix("allow ").and_then(|head| head.strip_suffix(" only")) {
+                (PolicyEffect::AllowOnly, permission)
+            } else {
+                return Err(invalid());
+            };
+
+            let pattern = pattern.trim();
+            if pattern.is_empty() || pattern.contains(char::is_whitespace) {
+                return Err(invalid());
+            }
+
+            rules.push(PolicyRule {
+                effect,
+                permission: Permission::parse(permission.trim())
+                    .map_err(|err| format!("line {}: {}", number + 1, err))?,
+                pattern: pattern.to_string(),
+            });
+        }
+
+        Ok(Policy { rules })
+    }
+
+    /// Returns the rule that denies `permission` for the given call stack.
+    /// When several rules apply, the same one is reported whatever their order.
+    pub fn denies(&self, permission: &Permission, caller_chain: &[String]) -> Option<&PolicyRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.denies(permission, caller_chain))
+            .min_by_key(|rule| rule.to_string())
+    }
+}
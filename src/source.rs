@@ -85,6 +85,12 @@ impl Source {
         }
     }
 
+    /// Returns the 1-based line number of the current position.
+    pub fn line(&self) -> usize {
+        let end = self.pos.min(self.src.len());
+        self.src[..end].iter().filter(|c| **c == '\n').count() + 1
+    }
+
     pub fn has_next(&self) -> bool {
         self.src.len() > self.pos
     }
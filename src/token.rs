@@ -5,8 +5,12 @@ pub enum TokenKind {
     RESERVED,
     IDENT,
     NUMBER,
+    FLOAT,
     EOF,
     TEXT,
+    /// Starts an interpolated string. It is followed by `num` parts, each a
+    /// `TEXT` token or a parenthesized expression.
+    TEMPLATE,
 }
 
 #[derive(Clone, Debug)]
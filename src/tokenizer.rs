@@ -84,6 +84,17 @@ impl Tokenizer {
         Ok(val)
     }
 
+    pub fn expect_float(&mut self) -> Result<f64, String> {
+        let kind = self.current_token().kind;
+        debug!("Expect FLOAT {:?}", self.current_token());
+        if kind != TokenKind::FLOAT {
+            return Err(format!("Unexpected type : {:?}", kind));
+        }
+        let val = self.current_token().str.parse().map_err(|_| format!("Invalid float literal: {}", self.current_token().str))?;
+        self.cursor += 1;
+        Ok(val)
+    }
+
     pub fn new() -> Tokenizer {
         Tokenizer {
             cursor: 0,
@@ -109,17 +120,33 @@ impl Tokenizer {
 
     pub fn tokenize(&mut self, source: &mut Source) -> Result<Vec<Token>, String> {
         let reserved: Vec<String> = vec![
+            String::from("<<="),
+            String::from(">>="),
             String::from("+="),
             String::from("-="),
             String::from("*="),
             String::from("/="),
+            String::from("%="),
+            String::from("&="),
+            String::from("|="),
+            String::from("^="),
+            String::from("->"),
+            String::from("=>"),
+            String::from("::"),
             String::from("$"),
             String::from("+"),
             String::from("-"),
             String::from("*"),
             String::from("/"),
+            String::from("%"),
             String::from("&&"),
+            String::from("||"),
             String::from("&"),
+            String::from("|"),
+            String::from("^"),
+            String::from("~"),
+            String::from("<<"),
+            String::from(">>"),
             String::from("{"),
             String::from("}"),
             String::from("("),
@@ -128,6 +155,7 @@ impl Tokenizer {
             String::from("]"),
             String::from("=="),
             String::from("!="),
+            String::from("!"),
             String::from(">="),
             String::from("<="),
             String::from("<"),
@@ -136,9 +164,9 @@ impl Tokenizer {
             String::from(";"),
             String::from(":"),
             String::from(","),
+            String::from("."),
             String::from("\""),
             String::from("fn"),
-            String::from("->"),
         ];
 
         let controls: Vec<String> = vec![
@@ -146,16 +174,60 @@ impl Tokenizer {
             String::from("while"),
             String::from("if"),
             String::from("else"),
+            String::from("break"),
+            String::from("continue"),
         ];
 
         while source.has_next() {
             match source.get_char(is('"')) {
                 Ok(_) => {
-                    let text = match source.get_chars(not(is('"'))) {
-                        Ok(t) => t,
-                        Err(_) => String::from(""),
-                    };
-                    source.get_char(is('"'))?;
+                    let parts = read_text(source)?;
+                    if let [TextPart::Text(text)] = parts.as_slice() {
+                        self.tokens.push(Token {
+                            kind: TokenKind::TEXT,
+                            str: text.clone(),
+                            num: 0
+                        });
+                        continue;
+                    }
+
+                    self.tokens.push(Token {
+                        kind: TokenKind::TEMPLATE,
+                        str: String::default(),
+                        num: parts.len()
+                    });
+                    for part in parts {
+                        match part {
+                            TextPart::Text(text) => self.tokens.push(Token {
+                                kind: TokenKind::TEXT,
+                                str: text,
+                                num: 0
+                            }),
+                            TextPart::Expr { start, end } => {
+                                // Tokenize the expression in place so that errors
+                                // report the line it is on.
+                                let mut expr = Source {
+                                    src: source.src[..end].to_vec(),
+                                    pos: start,
+                                };
+                                let mut tokenizer = Tokenizer::new();
+                                let mut tokens = tokenizer.tokenize(&mut expr)?;
+                                tokens.pop();
+                                self.tokens.push(Tokenizer::create_reserved(String::from("(")));
+                                self.tokens.extend(tokens);
+                                self.tokens.push(Tokenizer::create_reserved(String::from(")")));
+                            }
+                        }
+                    }
+                    continue;
+                }
+                Err(_) => {}
+            }
+            let start = source.pos;
+            if source.get_char(is('r')).is_ok() {
+                let hashes = source.get_chars(is('#')).map(|hashes| hashes.len()).unwrap_or(0);
+                if source.get_char(is('"')).is_ok() {
+                    let text = read_raw_text(source, hashes)?;
                     self.tokens.push(Token {
                         kind: TokenKind::TEXT,
                         str: text,
@@ -163,7 +235,7 @@ impl Tokenizer {
                     });
                     continue;
                 }
-                Err(_) => {}
+                source.pos = start;
             }
             match source.get_char(is_whitespace) {
                 Ok(_) => {
@@ -179,13 +251,25 @@ impl Tokenizer {
                 }
                 Err(_) => {}
             }
-            match source.get_chars(is_digit) {
-                Ok(num) => {
-                    self.tokens
-                        .push(Tokenizer::create_number(num.parse().unwrap()));
-                    continue;
+            if let Ok(num) = source.get_chars(is_digit) {
+                let fraction_pos = source.pos;
+                if source.get_char(is('.')).is_ok() {
+                    if let Ok(fraction) = source.get_chars(is_digit) {
+                        self.tokens.push(Token {
+                            kind: TokenKind::FLOAT,
+                            str: format!("{}.{}", num, fraction),
+                            num: 0,
+                        });
+                        continue;
+                    }
+                    source.pos = fraction_pos;
                 }
-                Err(_) => {}
+
+                match num.parse() {
+                    Ok(num) => self.tokens.push(Tokenizer::create_number(num)),
+                    Err(_) => return Err(format!("Number literal out of range: {}", num)),
+                }
+                continue;
             }
             match source.get_chars(or(is_ascii, or(is_digit, is('_')))) {
                 Ok(c) => {
@@ -216,7 +300,11 @@ impl Tokenizer {
                 }
                 Err(_) => {}
             }
-            return Err(String::from("Failed to tokenize"));
+            return Err(format!(
+                "line {}: unexpected character `{}`.",
+                source.line(),
+                source.src[source.pos]
+            ));
         }
 
         self.tokens.push(Token {
@@ -229,6 +317,134 @@ impl Tokenizer {
     }
 }
 
+enum TextPart {
+    Text(String),
+    /// An interpolated expression at `source.src[start..end]`.
+    Expr { start: usize, end: usize },
+}
+
+/// Reads a string literal after its opening quote. A backslash at the end of
+/// a line skips the line break and the indentation of the next line, and
+/// `{expr}` interpolates an expression; `{{` and `}}` stand for braces.
+fn read_text(source: &mut Source) -> Result<Vec<TextPart>, String> {
+    let line = source.line();
+    let unterminated = || format!("line {}: unterminated string literal.", line);
+    let mut parts = vec![];
+    let mut text = String::new();
+    loop {
+        match source.get_next() {
+            Ok('"') => {
+                if !text.is_empty() || parts.is_empty() {
+                    parts.push(TextPart::Text(text));
+                }
+                return Ok(parts);
+            }
+            Ok('{') if source.get_char(is('{')).is_ok() => text.push('{'),
+            Ok('}') if source.get_char(is('}')).is_ok() => text.push('}'),
+            Ok('{') => {
+                if !text.is_empty() {
+                    parts.push(TextPart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(read_interpolation(source)?);
+            }
+            Ok('}') => {
+                return Err(format!(
+                    "line {}: unmatched `}}` in string literal; use `}}}}` for a brace.",
+                    source.line()
+                ))
+            }
+            Ok('\\') => match source.get_next().map_err(|_| unterminated())? {
+                'n' => text.push('\n'),
+                't' => text.push('\t'),
+                'r' => text.push('\r'),
+                '0' => text.push('\0'),
+                c @ ('\\' | '"' | '\'') => text.push(c),
+                '\n' | '\r' => {
+                    let _ = source.get_chars(char::is_whitespace);
+                }
+                'u' => text.push(read_unicode_escape(source)?),
+                c => {
+                    return Err(format!(
+                        "line {}: unknown escape sequence `\\{}`.",
+                        source.line(),
+                        c
+                    ))
+                }
+            },
+            Ok(c) => text.push(c),
+            Err(_) => return Err(unterminated()),
+        }
+    }
+}
+
+/// Finds the end of an interpolated expression after its opening brace. The
+/// expression may contain string literals of its own.
+fn read_interpolation(source: &mut Source) -> Result<TextPart, String> {
+    let line = source.line();
+    let unterminated = || format!("line {}: unterminated interpolation in string literal.", line);
+    let start = source.pos;
+    let mut depth = 0;
+    loop {
+        match source.get_next() {
+            Ok('{') => depth += 1,
+            Ok('}') if depth > 0 => depth -= 1,
+            Ok('}') => break,
+            Ok('"') => loop {
+                match source.get_next() {
+                    Ok('\\') => {
+                        let _ = source.get_next();
+                    }
+                    Ok('"') => break,
+                    Ok(_) => {}
+                    Err(_) => return Err(unterminated()),
+                }
+            },
+            Ok(_) => {}
+            Err(_) => return Err(unterminated()),
+        }
+    }
+
+    let end = source.pos - 1;
+    if source.src[start..end].iter().all(|c| c.is_whitespace()) {
+        return Err(format!("line {}: empty interpolation in string literal.", line));
+    }
+    Ok(TextPart::Expr { start, end })
+}
+
+/// Reads the `{...}` part of a `\u{...}` escape: up to six hex digits naming
+/// a Unicode scalar value.
+fn read_unicode_escape(source: &mut Source) -> Result<char, String> {
+    let line = source.line();
+    let invalid = || format!("line {}: invalid unicode escape.", line);
+    source.get_char(is('{')).map_err(|_| invalid())?;
+    let digits = source.get_chars(|c| c.is_ascii_hexdigit()).map_err(|_| invalid())?;
+    source.get_char(is('}')).map_err(|_| invalid())?;
+    if digits.len() > 6 {
+        return Err(invalid());
+    }
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(invalid)
+}
+
+/// Reads a raw string literal after its opening quote. Nothing is escaped;
+/// the literal ends at a quote followed by as many `#` as it started with.
+fn read_raw_text(source: &mut Source, hashes: usize) -> Result<String, String> {
+    let line = source.line();
+    let terminator = format!("\"{}", "#".repeat(hashes));
+    let mut text = String::new();
+    loop {
+        if source.get_string(terminator.clone()).is_ok() {
+            return Ok(text);
+        }
+        match source.get_next() {
+            Ok(c) => text.push(c),
+            Err(_) => return Err(format!("line {}: unterminated raw string literal.", line)),
+        }
+    }
+}
+
 fn contains_list_chars(source: &mut Source, list: Vec<String>) -> Result<String, String> {
     for target in list {
         match source.get_string(target) {
@@ -249,10 +465,6 @@ fn is(ch: char) -> impl Fn(char) -> bool {
     move |c| c == ch
 }
 
-fn not(f: impl Fn(char) -> bool) -> impl Fn(char) -> bool {
-    move |c| !f(c)
-}
-
 fn is_whitespace(c: char) -> bool {
     c.is_whitespace()
 }
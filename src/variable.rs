@@ -1,14 +1,317 @@
+use crate::permission::Permission;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use uuid::Uuid;
+
+/// A key of a `map`. Numbers of any integer type become `Int`, so `1` and
+/// `to_i64(1)` name the same entry.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum MapKey {
+    Int(i64),
+    Text(String),
+}
+
+impl MapKey {
+    pub fn new(key: &Variable) -> Result<MapKey, String> {
+        match key {
+            Variable::Number { value } => i64::try_from(*value)
+                .map(MapKey::Int)
+                .map_err(|_| format!("Map key out of range: {}", value)),
+            Variable::Int { value } => Ok(MapKey::Int(*value)),
+            Variable::Text { value } => Ok(MapKey::Text(value.clone())),
+            _ => Err(format!("Map keys must be numbers or strings, found `{}`.", key.type_name())),
+        }
+    }
+
+    pub fn to_variable(&self) -> Variable {
+        match self {
+            MapKey::Int(value) => Variable::Int { value: *value },
+            MapKey::Text(value) => Variable::Text { value: value.clone() },
+        }
+    }
+}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapKey::Int(value) => write!(f, "{}", value),
+            MapKey::Text(value) => write!(f, "{:?}", value),
+        }
+    }
+}
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum Variable {
     Number {
         value: usize,
     },
+    Int {
+        value: i64,
+    },
+    Float {
+        value: f64,
+    },
+    Bool {
+        value: bool,
+    },
     Text {
         value: String,
     },
+    List {
+        value: Vec<Variable>,
+    },
+    Map {
+        value: BTreeMap<MapKey, Variable>,
+    },
+    /// A value of a script-defined struct, with its fields in declaration
+    /// order.
+    Struct {
+        name: String,
+        fields: Vec<(String, Variable)>,
+    },
+    /// A variant of a script-defined enum with its payload.
+    Enum {
+        name: String,
+        variant: String,
+        values: Vec<Variable>,
+    },
     Return {
         value: Box<Variable>
     },
+    /// Signals a `break` leaving the enclosing loops up to the one named
+    /// `label`, or the innermost loop.
+    Break {
+        label: Option<String>,
+    },
+    /// Signals a `continue`, like `Break`.
+    Continue {
+        label: Option<String>,
+    },
+    /// An unforgeable grant of `permission`, minted by the VM. Scripts can
+    /// only obtain one from `mint` and pass it along.
+    Capability {
+        permission: Permission,
+        id: Uuid,
+    },
     None {}
 }
+
+impl Variable {
+    pub fn type_name(&self) -> &str {
+        match self {
+            Variable::Number { .. } => "num",
+            Variable::Int { .. } => "i64",
+            Variable::Float { .. } => "f64",
+            Variable::Bool { .. } => "bool",
+            Variable::Text { .. } => "String",
+            Variable::List { .. } => "list",
+            Variable::Map { .. } => "map",
+            Variable::Struct { name, .. } => name,
+            Variable::Enum { name, .. } => name,
+            Variable::Return { .. } => "return",
+            Variable::Break { .. } => "break",
+            Variable::Continue { .. } => "continue",
+            Variable::Capability { .. } => "cap",
+            Variable::None {} => "none",
+        }
+    }
+
+    pub fn default_value(var_type: &str) -> Option<Variable> {
+        match var_type {
+            "num" => Some(Variable::Number { value: 0 }),
+            "i64" => Some(Variable::Int { value: 0 }),
+            "f64" => Some(Variable::Float { value: 0.0 }),
+            "bool" => Some(Variable::Bool { value: false }),
+            "String" => Some(Variable::Text { value: String::default() }),
+            "list" => Some(Variable::List { value: vec![] }),
+            "map" => Some(Variable::Map { value: BTreeMap::new() }),
+            "cap" => Some(Variable::None {}),
+            _ => None,
+        }
+    }
+
+    /// Returns true for the values a statement produces to leave the
+    /// statements around it: `return`, `break` and `continue`.
+    pub fn is_control_flow(&self) -> bool {
+        matches!(self, Variable::Return { .. } | Variable::Break { .. } | Variable::Continue { .. })
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Variable::Number { .. } | Variable::Int { .. } | Variable::Float { .. })
+    }
+
+    /// Returns whether a condition holds. Only booleans and numbers can be
+    /// used as conditions; a number is true when it is not zero.
+    pub fn is_truthy(&self) -> Result<bool, String> {
+        match self {
+            Variable::Bool { value } => Ok(*value),
+            Variable::Number { value } => Ok(*value != 0),
+            Variable::Int { value } => Ok(*value != 0),
+            Variable::Float { value } => Ok(*value != 0.0),
+            _ => Err(format!("expected a condition of type `bool`, found `{}`.", self.type_name())),
+        }
+    }
+
+    fn list_index(index: &Variable, len: usize) -> Result<usize, String> {
+        let position = match index {
+            Variable::Number { value } => Some(*value),
+            Variable::Int { value } => usize::try_from(*value).ok(),
+            _ => return Err(format!("List indices must be integers, found `{}`.", index.type_name())),
+        };
+        match position {
+            Some(position) if position < len => Ok(position),
+            _ => Err(format!("Index out of bounds: the len is {} but the index is {}.", len, index)),
+        }
+    }
+
+    pub fn index(&self, index: &Variable) -> Result<Variable, String> {
+        match self {
+            Variable::List { value } => Ok(value[Variable::list_index(index, value.len())?].clone()),
+            Variable::Map { value } => {
+                let key = MapKey::new(index)?;
+                value.get(&key).cloned().ok_or_else(|| format!("Key not found: {}", key))
+            }
+            _ => Err(format!("Cannot index into a value of type `{}`.", self.type_name())),
+        }
+    }
+
+    pub fn index_mut(&mut self, index: &Variable) -> Result<&mut Variable, String> {
+        match self {
+            Variable::List { value } => {
+                let position = Variable::list_index(index, value.len())?;
+                Ok(&mut value[position])
+            }
+            Variable::Map { value } => {
+                let key = MapKey::new(index)?;
+                match value.get_mut(&key) {
+                    Some(value) => Ok(value),
+                    None => Err(format!("Key not found: {}", key)),
+                }
+            }
+            _ => Err(format!("Cannot index into a value of type `{}`.", self.type_name())),
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Result<Variable, String> {
+        let value = match self {
+            Variable::Struct { fields, .. } => fields.iter().find(|(field, _)| field == name),
+            _ => None,
+        };
+        value
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| format!("`{}` has no field `{}`.", self.type_name(), name))
+    }
+
+    pub fn field_mut(&mut self, name: &str) -> Result<&mut Variable, String> {
+        let type_name = self.type_name().to_string();
+        match self {
+            Variable::Struct { fields, .. } => fields
+                .iter_mut()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| format!("`{}` has no field `{}`.", type_name, name)),
+            _ => Err(format!("`{}` has no field `{}`.", type_name, name)),
+        }
+    }
+
+    /// Stores `item` at `index`. Maps insert missing keys; lists only
+    /// replace existing items.
+    pub fn set_index(&mut self, index: &Variable, item: Variable) -> Result<(), String> {
+        match self {
+            Variable::Map { value } => {
+                value.insert(MapKey::new(index)?, item);
+                Ok(())
+            }
+            _ => {
+                *self.index_mut(index)? = item;
+                Ok(())
+            }
+        }
+    }
+
+    /// Formats a value inside a list or a map, where strings are quoted.
+    fn to_item_string(&self) -> String {
+        match self {
+            Variable::Text { value } => format!("{:?}", value),
+            item => item.to_string(),
+        }
+    }
+
+    /// Converts a numeric value to `num`, `i64` or `f64`. Floats are
+    /// truncated toward zero.
+    pub fn convert(&self, var_type: &str) -> Result<Variable, String> {
+        let out_of_range = || format!("{} is out of range for `{}`.", self, var_type);
+        match (var_type, self) {
+            ("num", Variable::Number { value }) => Ok(Variable::Number { value: *value }),
+            ("num", Variable::Int { value }) => usize::try_from(*value)
+                .map(|value| Variable::Number { value })
+                .map_err(|_| out_of_range()),
+            ("num", Variable::Float { value }) => {
+                if value.is_finite() && *value > -1.0 && *value < usize::MAX as f64 {
+                    Ok(Variable::Number { value: *value as usize })
+                } else {
+                    Err(out_of_range())
+                }
+            }
+            ("i64", Variable::Number { value }) => i64::try_from(*value)
+                .map(|value| Variable::Int { value })
+                .map_err(|_| out_of_range()),
+            ("i64", Variable::Int { value }) => Ok(Variable::Int { value: *value }),
+            ("i64", Variable::Float { value }) => {
+                if value.is_finite() && *value > i64::MIN as f64 - 1.0 && *value < i64::MAX as f64 {
+                    Ok(Variable::Int { value: *value as i64 })
+                } else {
+                    Err(out_of_range())
+                }
+            }
+            ("f64", Variable::Number { value }) => Ok(Variable::Float { value: *value as f64 }),
+            ("f64", Variable::Int { value }) => Ok(Variable::Float { value: *value as f64 }),
+            ("f64", Variable::Float { value }) => Ok(Variable::Float { value: *value }),
+            _ => Err(format!("Cannot convert `{}` to `{}`.", self.type_name(), var_type)),
+        }
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variable::Number { value } => write!(f, "{}", value),
+            Variable::Int { value } => write!(f, "{}", value),
+            Variable::Float { value } => write!(f, "{:?}", value),
+            Variable::Bool { value } => write!(f, "{}", value),
+            Variable::Text { value } => write!(f, "{}", value),
+            Variable::List { value } => {
+                let items: Vec<String> = value.iter().map(Variable::to_item_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Variable::Map { value } => {
+                let entries: Vec<String> = value
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value.to_item_string()))
+                    .collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            Variable::Struct { name, fields } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| format!("{}: {}", field, value.to_item_string()))
+                    .collect();
+                write!(f, "{} {{ {} }}", name, fields.join(", "))
+            }
+            Variable::Enum { name, variant, values } => {
+                write!(f, "{}::{}", name, variant)?;
+                if !values.is_empty() {
+                    let values: Vec<String> = values.iter().map(Variable::to_item_string).collect();
+                    write!(f, "({})", values.join(", "))?;
+                }
+                Ok(())
+            }
+            Variable::Return { value } => write!(f, "{}", value),
+            Variable::Break { .. } => write!(f, "break"),
+            Variable::Continue { .. } => write!(f, "continue"),
+            Variable::Capability { permission, .. } => write!(f, "<capability {}>", permission),
+            Variable::None {} => write!(f, "none"),
+        }
+    }
+}
@@ -1,10 +1,15 @@
-use crate::external_function::{ExternalFuncReturn, ExternalFuncStatus};
+use crate::external_function::{std_func_permissions, ExternalFuncReturn, ExternalFuncStatus};
+use crate::manifest::Manifest;
 use crate::node::*;
-use crate::permission::Permission;
+use crate::policy::Policy;
+use crate::permission::{Permission, PermissionDecision, PermissionEvent, PermissionRegistry, PromptDecision};
 use crate::source::Source;
 use crate::variable::*;
-use std::collections::{HashMap, VecDeque};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::convert::TryFrom;
 use std::string::*;
+use uuid::Uuid;
 
 #[derive(Clone, Debug)]
 pub struct Block {
@@ -14,18 +19,69 @@ pub struct Block {
     pub is_split: bool
 }
 
+impl Block {
+    pub fn holds(&self, permission: &Permission) -> bool {
+        permission.is_granted(&self.accept, &self.reject)
+    }
+}
+
+pub type AuditSink = Box<dyn FnMut(&PermissionEvent)>;
+pub type PermissionPrompt = Box<dyn FnMut(&Permission, &str) -> PromptDecision>;
+
+/// Functions implemented by the VM itself. They need no permission and can
+/// be shadowed by script functions of the same name.
+pub const BUILTIN_FUNCTIONS: &[&str] = &["len", "push", "pop", "remove", "keys", "contains"];
+
+/// One step of an assignment target, as in `xs[0]` or `p.x`.
+enum Accessor {
+    Index(Variable),
+    Field(String),
+}
+
+/// What a loop does after one run of its body.
+enum Flow {
+    Next,
+    Exit,
+    /// Leave the loop and hand the value to the statements around it.
+    Propagate(Variable),
+}
+
 pub struct GPSL {
     pub functions: Option<HashMap<String, Box<Node>>>,
     pub global_variables: Vec<Variable>,
     pub source: Source,
     pub blocks: VecDeque<Block>,
-    pub external_func: Vec<fn(String, Vec<Variable>, Vec<Permission>, Vec<Permission>) -> ExternalFuncReturn>
+    pub external_func: Vec<fn(String, Vec<Variable>, Vec<Permission>, Vec<Permission>) -> ExternalFuncReturn>,
+    pub root_accept: Vec<Permission>,
+    pub root_reject: Vec<Permission>,
+    pub permissions: PermissionRegistry,
+    pub external_permissions: HashMap<String, Vec<Permission>>,
+    pub call_stack: Vec<String>,
+    pub audit_sink: Option<AuditSink>,
+    /// Remaining number of external calls allowed under each permission.
+    /// A quota on `Net` also counts calls that need `Net.Http`.
+    pub quotas: HashMap<Permission, usize>,
+    /// Consulted with the permission and the requesting function when a
+    /// script needs a permission its block neither accepts nor rejects.
+    pub prompt: Option<PermissionPrompt>,
+    pub always_allowed: Vec<Permission>,
+    /// The grants of the manifest applied with `apply_manifest`. Prompts
+    /// cannot grant anything outside of them.
+    pub manifest_cap: Option<Vec<Permission>>,
+    pub policy: Policy,
+    /// When set, external functions are granted only the permissions of the
+    /// capabilities passed to them instead of the block's `accept` list.
+    pub capability_mode: bool,
+    pub capabilities: HashMap<Uuid, Permission>
 }
 
 #[derive(Clone, Debug)]
 pub struct LocalVariable {
     pub name: String,
     pub value: Variable,
+    /// The type given by `let` or the function signature. Bindings of a
+    /// `match` pattern have none.
+    pub var_type: Option<String>,
     pub status: VariableStatus,
 }
 
@@ -47,10 +103,30 @@ impl GPSL {
             functions,
             global_variables: vec![],
             blocks: VecDeque::new(),
-            external_func
+            external_func,
+            root_accept: vec![Permission::administrator(), Permission::std_io()],
+            root_reject: vec![],
+            permissions: PermissionRegistry::new(),
+            external_permissions: std_func_permissions(),
+            call_stack: vec![],
+            audit_sink: None,
+            quotas: HashMap::new(),
+            prompt: None,
+            always_allowed: vec![],
+            manifest_cap: None,
+            policy: Policy::default(),
+            capability_mode: false,
+            capabilities: HashMap::new()
         }
     }
 
+    /// Sets the permissions of the root block pushed by `run`.
+    pub fn with_permissions(mut self, accept: Vec<Permission>, reject: Vec<Permission>) -> GPSL {
+        self.root_accept = accept;
+        self.root_reject = reject;
+        self
+    }
+
     pub fn get_local_var_mut(&mut self, name: &String) -> Option<&mut LocalVariable> {
         for x in 0..self.blocks.len() {
             if self.blocks[x].variables.contains_key(name) {
@@ -92,70 +168,746 @@ impl GPSL {
         }
     }
 
+    /// Converts two numeric operands to a common type: `f64` if either is a
+    /// float, otherwise `i64` if either is signed.
+    pub fn promote(lhs: Variable, rhs: Variable) -> Result<(Variable, Variable), String> {
+        if !lhs.is_numeric() || !rhs.is_numeric() {
+            return Err(String::from("Not a number"));
+        }
+
+        let var_type = match (&lhs, &rhs) {
+            (Variable::Float { .. }, _) | (_, Variable::Float { .. }) => "f64",
+            (Variable::Int { .. }, _) | (_, Variable::Int { .. }) => "i64",
+            _ => return Ok((lhs, rhs)),
+        };
+        Ok((lhs.convert(var_type)?, rhs.convert(var_type)?))
+    }
+
+    fn arithmetic_error(kind: &NodeKind, lhs: &Variable, rhs: &Variable) -> String {
+        let symbol = match kind {
+            NodeKind::ADD => "+",
+            NodeKind::SUB => "-",
+            NodeKind::MUL => "*",
+            NodeKind::MOD => "%",
+            NodeKind::SHL => "<<",
+            NodeKind::SHR => ">>",
+            _ => "/",
+        };
+        let zero = matches!(rhs, Variable::Number { value: 0 } | Variable::Int { value: 0 })
+            || matches!(rhs, Variable::Float { value } if *value == 0.0);
+        if (*kind == NodeKind::DIV || *kind == NodeKind::MOD) && zero {
+            format!("Division by zero: {} {} {}", lhs, symbol, rhs)
+        } else {
+            format!("Arithmetic overflow: {} {} {}", lhs, symbol, rhs)
+        }
+    }
+
+    pub fn arithmetic(kind: &NodeKind, lhs: Variable, rhs: Variable) -> Result<Variable, String> {
+        if let (NodeKind::ADD, Variable::Text { value: l }, Variable::Text { value: r }) = (kind, &lhs, &rhs) {
+            return Ok(Variable::Text { value: format!("{}{}", l, r) });
+        }
+        let (lhs, rhs) = GPSL::promote(lhs, rhs)?;
+        let value = match (&lhs, &rhs) {
+            (Variable::Number { value: l }, Variable::Number { value: r }) => match kind {
+                NodeKind::ADD => l.checked_add(*r),
+                NodeKind::SUB => l.checked_sub(*r),
+                NodeKind::MUL => l.checked_mul(*r),
+                NodeKind::MOD => l.checked_rem(*r),
+                _ => l.checked_div(*r),
+            }
+            .map(|value| Variable::Number { value }),
+            (Variable::Int { value: l }, Variable::Int { value: r }) => match kind {
+                NodeKind::ADD => l.checked_add(*r),
+                NodeKind::SUB => l.checked_sub(*r),
+                NodeKind::MUL => l.checked_mul(*r),
+                NodeKind::MOD => l.checked_rem(*r),
+                _ => l.checked_div(*r),
+            }
+            .map(|value| Variable::Int { value }),
+            (Variable::Float { value: l }, Variable::Float { value: r }) => match kind {
+                NodeKind::ADD => Some(l + r),
+                NodeKind::SUB => Some(l - r),
+                NodeKind::MUL => Some(l * r),
+                _ if *r == 0.0 => None,
+                NodeKind::MOD => Some(l % r),
+                _ => Some(l / r),
+            }
+            .map(|value| Variable::Float { value }),
+            _ => return Err(String::from("Not a number")),
+        };
+
+        value.ok_or_else(|| GPSL::arithmetic_error(kind, &lhs, &rhs))
+    }
+
+    fn integer_error(value: &Variable) -> String {
+        format!("Bitwise operators need integers, found `{}`.", value.type_name())
+    }
+
+    /// Applies `&`, `|`, `^`, `<<` or `>>`. A shift fails when its amount is
+    /// negative or not smaller than the width of the shifted value.
+    pub fn bitwise(kind: &NodeKind, lhs: Variable, rhs: Variable) -> Result<Variable, String> {
+        if *kind == NodeKind::SHL || *kind == NodeKind::SHR {
+            let amount = match &rhs {
+                Variable::Number { value } => u32::try_from(*value).ok(),
+                Variable::Int { value } => u32::try_from(*value).ok(),
+                _ => return Err(GPSL::integer_error(&rhs)),
+            };
+            let value = match (&lhs, amount) {
+                (Variable::Number { value }, Some(amount)) => match kind {
+                    NodeKind::SHL => value.checked_shl(amount),
+                    _ => value.checked_shr(amount),
+                }
+                .map(|value| Variable::Number { value }),
+                (Variable::Int { value }, Some(amount)) => match kind {
+                    NodeKind::SHL => value.checked_shl(amount),
+                    _ => value.checked_shr(amount),
+                }
+                .map(|value| Variable::Int { value }),
+                (Variable::Number { .. } | Variable::Int { .. }, None) => None,
+                _ => return Err(GPSL::integer_error(&lhs)),
+            };
+            return value.ok_or_else(|| GPSL::arithmetic_error(kind, &lhs, &rhs));
+        }
+
+        match GPSL::promote(lhs, rhs)? {
+            (Variable::Number { value: l }, Variable::Number { value: r }) => Ok(Variable::Number {
+                value: match kind {
+                    NodeKind::BITAND => l & r,
+                    NodeKind::BITOR => l | r,
+                    _ => l ^ r,
+                },
+            }),
+            (Variable::Int { value: l }, Variable::Int { value: r }) => Ok(Variable::Int {
+                value: match kind {
+                    NodeKind::BITAND => l & r,
+                    NodeKind::BITOR => l | r,
+                    _ => l ^ r,
+                },
+            }),
+            (lhs, _) => Err(GPSL::integer_error(&lhs)),
+        }
+    }
+
+    pub fn compare(kind: &NodeKind, lhs: Variable, rhs: Variable) -> Result<Variable, String> {
+        let (lhs, rhs) = if lhs.is_numeric() && rhs.is_numeric() {
+            GPSL::promote(lhs, rhs)?
+        } else {
+            (lhs, rhs)
+        };
+
+        let ordering = match (&lhs, &rhs) {
+            (Variable::Number { value: lhs }, Variable::Number { value: rhs }) => lhs.partial_cmp(rhs),
+            (Variable::Int { value: lhs }, Variable::Int { value: rhs }) => lhs.partial_cmp(rhs),
+            (Variable::Float { value: lhs }, Variable::Float { value: rhs }) => lhs.partial_cmp(rhs),
+            (Variable::Text { value: lhs }, Variable::Text { value: rhs }) => lhs.partial_cmp(rhs),
+            _ => None,
+        };
+        let orderable = (lhs.is_numeric() && rhs.is_numeric()) || matches!((&lhs, &rhs), (Variable::Text { .. }, Variable::Text { .. }));
+
+        let result = match kind {
+            NodeKind::EQ => lhs == rhs,
+            NodeKind::NE => lhs != rhs,
+            NodeKind::LT | NodeKind::LE if !orderable => {
+                return Err(format!("Cannot compare `{}` with `{}`.", lhs.type_name(), rhs.type_name()))
+            }
+            NodeKind::LT => ordering == Some(Ordering::Less),
+            _ => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+        };
+        Ok(Variable::Bool { value: result })
+    }
+
+    pub fn struct_fields(&self, name: &str) -> Option<&Vec<(String, String)>> {
+        match self.functions.as_ref()?.get(name).map(|node| &**node) {
+            Some(Node::Struct { fields, .. }) => Some(fields),
+            _ => None,
+        }
+    }
+
+    pub fn enum_variants(&self, name: &str) -> Option<&Vec<(String, Vec<String>)>> {
+        match self.functions.as_ref()?.get(name).map(|node| &**node) {
+            Some(Node::Enum { variants, .. }) => Some(variants),
+            _ => None,
+        }
+    }
+
+    fn is_declared_type(&self, var_type: &str) -> bool {
+        Variable::default_value(var_type).is_some()
+            || self.struct_fields(var_type).is_some()
+            || self.enum_variants(var_type).is_some()
+    }
+
+    pub fn field_type(&self, struct_name: &str, field: &str) -> Result<String, String> {
+        self.struct_fields(struct_name)
+            .and_then(|fields| fields.iter().find(|(name, _)| name == field))
+            .map(|(_, field_type)| field_type.clone())
+            .ok_or_else(|| format!("`{}` has no field `{}`.", struct_name, field))
+    }
+
+    /// Returns the initial value of a variable of type `var_type`. A struct
+    /// starts with the initial value of each of its fields, and an enum is
+    /// `none` until assigned.
+    pub fn default_value(&self, var_type: &str) -> Result<Variable, String> {
+        self.default_value_within(var_type, &[])
+    }
+
+    fn default_value_within(&self, var_type: &str, outer: &[&str]) -> Result<Variable, String> {
+        if let Some(value) = Variable::default_value(var_type) {
+            return Ok(value);
+        }
+        if self.enum_variants(var_type).is_some() {
+            return Ok(Variable::None {});
+        }
+
+        let fields = self
+            .struct_fields(var_type)
+            .ok_or_else(|| format!("{}: 未知の型です。", var_type))?;
+        if outer.contains(&var_type) {
+            return Err(format!("{}: a struct cannot contain itself.", var_type));
+        }
+
+        let outer = [outer, &[var_type]].concat();
+        let mut values = vec![];
+        for (name, field_type) in fields {
+            values.push((name.clone(), self.default_value_within(field_type, &outer)?));
+        }
+        Ok(Variable::Struct {
+            name: var_type.to_string(),
+            fields: values,
+        })
+    }
+
+    pub fn check_type(&self, var_type: &str, value: Variable) -> Result<Variable, String> {
+        if !self.is_declared_type(var_type) {
+            return Err(format!("{}: 未知の型です。", var_type));
+        }
+
+        if value.type_name() == var_type {
+            Ok(value)
+        } else if matches!(value, Variable::Number { .. }) && (var_type == "i64" || var_type == "f64") {
+            value.convert(var_type)
+        } else {
+            Err(format!("mismatched types: expected `{}`, found `{}`.", var_type, value.type_name()))
+        }
+    }
+
+    pub fn check_return(&self, function_name: &str, return_type: &Option<String>, value: Option<Variable>) -> Result<Option<Variable>, String> {
+        match (return_type, value) {
+            (Some(return_type), Some(value)) => self.check_type(return_type, value)
+                .map(Some)
+                .map_err(|err| format!("{}: return value: {}", function_name, err)),
+            (Some(return_type), None) => Err(format!(
+                "{}: expected a return value of type `{}`.",
+                function_name, return_type
+            )),
+            (None, value) => Ok(value),
+        }
+    }
+
+    /// Caps the root permissions to `manifest` and rejects scripts whose
+    /// permission blocks ask for more than it grants.
+    pub fn apply_manifest(&mut self, manifest: &Manifest) -> Result<(), String> {
+        for permission in manifest.accept.iter().chain(&manifest.reject) {
+            self.permissions.check(permission)?;
+        }
+        if let Some(functions) = &self.functions {
+            manifest.check(functions)?;
+        }
+
+        let mut accept: Vec<Permission> = vec![];
+        for permission in &self.root_accept {
+            if permission.is_granted(&manifest.accept, &[]) && !accept.contains(permission) {
+                accept.push(permission.clone());
+            }
+        }
+        for permission in &manifest.accept {
+            if permission.is_granted(&self.root_accept, &[]) && !accept.contains(permission) {
+                accept.push(permission.clone());
+            }
+        }
+        self.root_accept = accept;
+        self.manifest_cap = Some(manifest.accept.clone());
+
+        for permission in &manifest.reject {
+            if !self.root_reject.contains(permission) {
+                self.root_reject.push(permission.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the permissions of a nested block. A block can only drop
+    /// permissions held by its parent: anything the parent rejected stays
+    /// rejected, and an empty `accept` list inherits the parent's grants.
+    pub fn narrow_permissions(&mut self, parent: &Block, accept: &[Permission], reject: &[Permission]) -> Result<(Vec<Permission>, Vec<Permission>), String> {
+        let function_name = self.call_stack.last().cloned().unwrap_or_default();
+        let mut narrowed_accept = vec![];
+        for permission in accept {
+            self.permissions.check(permission)?;
+            if !parent.holds(permission) && !self.prompt_permission(parent, permission, &function_name) {
+                return Err(format!("Cannot widen permissions: {} is not granted by the enclosing block.", permission));
+            }
+            if !narrowed_accept.contains(permission) {
+                narrowed_accept.push(permission.clone());
+            }
+        }
+
+        if accept.is_empty() {
+            narrowed_accept = parent.accept.clone();
+        }
+
+        let mut narrowed_reject = parent.reject.clone();
+        for permission in reject {
+            self.permissions.check(permission)?;
+            if !narrowed_reject.contains(permission) {
+                narrowed_reject.push(permission.clone());
+            }
+        }
+
+        Ok((narrowed_accept, narrowed_reject))
+    }
+
+    /// Checks that the current block holds every permission the function
+    /// declares in its leading permission clause, and returns the ones that
+    /// were granted through the prompt.
+    pub fn check_function_permission(&mut self, function_name: &str, function: &Node) -> Result<Vec<Permission>, String> {
+        let mut prompted = vec![];
+        if let Some(Node::Permission { accept, .. }) = function.function_permission() {
+            let block = self.blocks.front().unwrap().clone();
+            for permission in accept {
+                self.permissions.check(permission)?;
+                if block.holds(permission) {
+                    continue;
+                }
+                if !self.prompt_permission(&block, permission, function_name) {
+                    return Err(format!("{}: missing permission {}.", function_name, permission));
+                }
+                prompted.push(permission.clone());
+            }
+        }
+        Ok(prompted)
+    }
+
+    /// Decides a permission the block neither accepts nor rejects, using the
+    /// answers remembered from earlier prompts or asking `prompt`. Nothing
+    /// outside of the manifest grants is ever allowed.
+    pub fn prompt_permission(&mut self, block: &Block, permission: &Permission, function_name: &str) -> bool {
+        if block.reject.iter().any(|reject| reject.implies(permission)) {
+            return false;
+        }
+        if let Some(cap) = &self.manifest_cap {
+            if !permission.is_granted(cap, &[]) {
+                return false;
+            }
+        }
+        if permission.is_granted(&self.always_allowed, &block.reject) {
+            return true;
+        }
+
+        match self.prompt.as_mut().map(|prompt| prompt(permission, function_name)) {
+            Some(PromptDecision::AllowOnce) => true,
+            Some(PromptDecision::AllowAlways) => {
+                self.always_allowed.push(permission.clone());
+                true
+            }
+            Some(PromptDecision::Deny) | None => false,
+        }
+    }
+
+    fn record_permission(&mut self, permission: Option<Permission>, function_name: &str, decision: PermissionDecision) {
+        if let Some(sink) = self.audit_sink.as_mut() {
+            sink(&PermissionEvent {
+                permission,
+                function: function_name.to_string(),
+                caller_chain: self.call_stack.clone(),
+                decision,
+            });
+        }
+    }
+
+    /// Mints a capability for `permission`, which the current block must hold.
+    pub fn mint_capability(&mut self, permission: &str) -> Result<Variable, String> {
+        let permission = Permission::parse(permission)?;
+        self.permissions.check(&permission)?;
+        if !self.blocks.front().unwrap().holds(&permission) {
+            return Err(format!("mint: missing permission {}.", permission));
+        }
+
+        let id = Uuid::new_v4();
+        self.capabilities.insert(id, permission.clone());
+        Ok(Variable::Capability { permission, id })
+    }
+
+    /// Returns the permissions of the capabilities in `args`, failing on any
+    /// capability this VM did not mint.
+    pub fn capability_permissions(&self, args: &[Variable]) -> Result<Vec<Permission>, String> {
+        let mut permissions = vec![];
+        for arg in args {
+            if let Variable::Capability { permission, id } = arg {
+                if self.capabilities.get(id) != Some(permission) {
+                    return Err(format!("Invalid capability for {}.", permission));
+                }
+                permissions.push(permission.clone());
+            }
+        }
+        Ok(permissions)
+    }
+
+    /// Calls an external function after checking the permissions listed for
+    /// it in `external_permissions`.
+    pub fn call_external(&mut self, function_name: String, args_value: Vec<Variable>) -> Result<Option<Variable>, String> {
+        debug!("Searching external: {}, ({:?})", &function_name, args_value);
+
+        let mut block = self.blocks.front().unwrap().clone();
+        let granted = self.capability_permissions(&args_value)?;
+        if self.capability_mode {
+            block.accept = granted;
+        }
+
+        let required = self.external_permissions.get(&function_name).cloned().unwrap_or_default();
+        for permission in &required {
+            if let Some(rule) = self.policy.denies(permission, &self.call_stack) {
+                let err = format!("{}: permission {} denied by policy rule `{}`.", function_name, permission, rule);
+                self.record_permission(Some(permission.clone()), &function_name, PermissionDecision::Rejected);
+                return Err(err);
+            }
+
+            if !permission.is_partially_granted(&block.accept, &block.reject) {
+                // In capability mode only the capability arguments grant
+                // permissions, so there is nothing to ask for.
+                if self.capability_mode || !self.prompt_permission(&block, permission, &function_name) {
+                    self.record_permission(Some(permission.clone()), &function_name, PermissionDecision::Rejected);
+                    return Err(format!("{}: permission {} rejected.", function_name, permission));
+                }
+                block.accept.push(permission.clone());
+            }
+
+            let exhausted = self
+                .quotas
+                .iter()
+                .find(|(quota, remaining)| quota.name_implies(permission) && **remaining == 0)
+                .map(|(quota, _)| quota.clone());
+            if let Some(quota) = exhausted {
+                self.record_permission(Some(permission.clone()), &function_name, PermissionDecision::Rejected);
+                return Err(format!("{}: quota for permission {} exhausted.", function_name, quota));
+            }
+        }
+
+        for func in self.external_func.clone() {
+            let res = func(function_name.clone(), args_value.clone(), block.accept.clone(), block.reject.clone());
+            // A function that fails was still granted its permissions and
+            // called, so the failure is recorded like a success.
+            let decision = match res.status {
+                ExternalFuncStatus::SUCCESS | ExternalFuncStatus::ERROR => PermissionDecision::Granted,
+                ExternalFuncStatus::REJECTED => PermissionDecision::Rejected,
+                ExternalFuncStatus::NOTFOUND => continue,
+            };
+
+            if required.is_empty() {
+                if decision == PermissionDecision::Rejected {
+                    self.record_permission(None, &function_name, decision);
+                }
+            } else {
+                for permission in &required {
+                    self.record_permission(Some(permission.clone()), &function_name, decision);
+                }
+            }
+
+            if res.status == ExternalFuncStatus::ERROR {
+                return match res.value {
+                    Some(Variable::Text { value }) => Err(value),
+                    _ => Err(format!("{}: external function failed.", function_name)),
+                };
+            }
+
+            return match decision {
+                PermissionDecision::Granted => {
+                    for (quota, remaining) in self.quotas.iter_mut() {
+                        if required.iter().any(|permission| quota.name_implies(permission)) {
+                            *remaining = remaining.saturating_sub(1);
+                        }
+                    }
+                    Ok(res.value)
+                }
+                PermissionDecision::Rejected => Err("External function rejected.".to_string()),
+            };
+        }
+
+        Err(format!("Function not found: {}", function_name))
+    }
+
+    /// Evaluates the indices of an assignable expression such as `xs[i].y`.
+    /// Returns the variable it starts from and the steps, outermost first.
+    fn place(&mut self, target: &Node) -> Result<(String, Vec<Accessor>), String> {
+        match target {
+            Node::Lvar { value } => Ok((value.clone(), vec![])),
+            Node::Index { target, index } => {
+                let (name, mut path) = self.place(target)?;
+                let index = self.evaluate(index.clone())?.ok_or_else(|| String::from("Index has no value."))?;
+                path.push(Accessor::Index(index));
+                Ok((name, path))
+            }
+            Node::Field { target, name: field } => {
+                let (name, mut path) = self.place(target)?;
+                path.push(Accessor::Field(field.clone()));
+                Ok((name, path))
+            }
+            _ => Err(String::from("Invalid assignment target.")),
+        }
+    }
+
+    fn place_mut(&mut self, name: &String, path: &[Accessor]) -> Result<&mut Variable, String> {
+        let mut value = &mut self
+            .get_local_var_mut(name)
+            .ok_or_else(|| format!("Undefined variable: {}", name))?
+            .value;
+        for accessor in path {
+            value = match accessor {
+                Accessor::Index(index) => value.index_mut(index)?,
+                Accessor::Field(field) => value.field_mut(field)?,
+            };
+        }
+        Ok(value)
+    }
+
+    pub fn assign(&mut self, target: &Node, value: Variable) -> Result<(), String> {
+        match target {
+            Node::Lvar { value: name } => {
+                let var_type = self
+                    .get_local_var_mut(name)
+                    .ok_or_else(|| format!("Undefined variable: {}", name))?
+                    .var_type
+                    .clone();
+                let value = match var_type {
+                    Some(var_type) => self.check_type(&var_type, value).map_err(|err| format!("{}: {}", name, err))?,
+                    None => value,
+                };
+                let variable = self.get_local_var_mut(name).unwrap();
+                variable.value = value;
+                variable.status.initialized = true;
+                Ok(())
+            }
+            _ => {
+                let (name, path) = self.place(target)?;
+                match path.split_last() {
+                    Some((Accessor::Index(index), path)) => self.place_mut(&name, path)?.set_index(index, value),
+                    Some((Accessor::Field(field), path)) => {
+                        let struct_name = self.place_mut(&name, path)?.type_name().to_string();
+                        let value = self
+                            .check_type(&self.field_type(&struct_name, field)?, value)
+                            .map_err(|err| format!("{}.{}: {}", struct_name, field, err))?;
+                        *self.place_mut(&name, path)?.field_mut(field)? = value;
+                        Ok(())
+                    }
+                    None => Err(String::from("Invalid assignment target.")),
+                }
+            }
+        }
+    }
+
+    /// Runs one of `BUILTIN_FUNCTIONS`. Functions that modify a collection
+    /// take an assignable expression as their first argument.
+    pub fn call_builtin(&mut self, name: &str, args: &[Box<Node>]) -> Result<Option<Variable>, String> {
+        let arity = match name {
+            "push" | "remove" | "contains" => 2,
+            _ => 1,
+        };
+        if args.len() != arity {
+            return Err(format!("{}: expected {} arguments, found {}.", name, arity, args.len()));
+        }
+
+        let mut values = vec![];
+        for arg in &args[1..] {
+            values.push(self.evaluate(arg.clone())?.unwrap_or(Variable::None {}));
+        }
+
+        match name {
+            "push" | "pop" | "remove" => {
+                let (target, path) = self.place(&args[0])?;
+                match (name, self.place_mut(&target, &path)?, values.pop()) {
+                    ("push", Variable::List { value }, Some(item)) => {
+                        value.push(item);
+                        Ok(None)
+                    }
+                    ("pop", Variable::List { value }, _) => value
+                        .pop()
+                        .map(Some)
+                        .ok_or_else(|| String::from("pop: the list is empty.")),
+                    ("remove", Variable::Map { value }, Some(key)) => {
+                        let key = MapKey::new(&key)?;
+                        match value.remove(&key) {
+                            Some(value) => Ok(Some(value)),
+                            None => Err(format!("remove: key not found: {}", key)),
+                        }
+                    }
+                    ("remove", value, _) => Err(format!("remove: expected a map, found `{}`.", value.type_name())),
+                    (name, value, _) => Err(format!("{}: expected a list, found `{}`.", name, value.type_name())),
+                }
+            }
+            _ => {
+                let collection = self.evaluate(args[0].clone())?.unwrap_or(Variable::None {});
+                match (name, &collection, values.first()) {
+                    ("len", Variable::List { value }, _) => Ok(Some(Variable::Number { value: value.len() })),
+                    ("len", Variable::Map { value }, _) => Ok(Some(Variable::Number { value: value.len() })),
+                    ("len", Variable::Text { value }, _) => Ok(Some(Variable::Number { value: value.chars().count() })),
+                    ("keys", Variable::Map { value }, _) => Ok(Some(Variable::List {
+                        value: value.keys().map(MapKey::to_variable).collect(),
+                    })),
+                    ("contains", Variable::Map { value }, Some(key)) => Ok(Some(Variable::Bool {
+                        value: value.contains_key(&MapKey::new(key)?),
+                    })),
+                    ("contains", Variable::List { value }, Some(item)) => Ok(Some(Variable::Bool {
+                        value: value.contains(item),
+                    })),
+                    ("len", value, _) => Err(format!(
+                        "len: expected a list, a map or a string, found `{}`.",
+                        value.type_name()
+                    )),
+                    ("keys", value, _) => Err(format!("keys: expected a map, found `{}`.", value.type_name())),
+                    (name, value, _) => Err(format!(
+                        "{}: expected a list or a map, found `{}`.",
+                        name,
+                        value.type_name()
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Matches `value` against `pattern`, collecting the variables it binds.
+    pub fn pattern_matches(pattern: &Pattern, value: &Variable, bindings: &mut Vec<(String, Variable)>) -> Result<bool, String> {
+        match pattern {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Binding(name) => {
+                bindings.push((name.clone(), value.clone()));
+                Ok(true)
+            }
+            Pattern::Literal(literal) => {
+                let equal = GPSL::compare(&NodeKind::EQ, value.clone(), literal.clone())?;
+                Ok(equal == Variable::Bool { value: true })
+            }
+            Pattern::Variant { name, variant, fields } => match value {
+                Variable::Enum { name: value_name, variant: value_variant, values }
+                    if value_name == name && value_variant == variant =>
+                {
+                    for (field, value) in fields.iter().zip(values) {
+                        if !GPSL::pattern_matches(field, value, bindings)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+        }
+    }
+
+    /// Decides how the loop named `label` continues after its body produced
+    /// `result`. A `break` or `continue` without a label, or with this
+    /// loop's label, applies here; any other control flow leaves the loop.
+    fn loop_flow(label: &Option<String>, result: Option<Variable>) -> Flow {
+        match result {
+            Some(Variable::Break { label: target }) if target.is_none() || target == *label => Flow::Exit,
+            Some(Variable::Continue { label: target }) if target.is_none() || target == *label => Flow::Next,
+            Some(res) if res.is_control_flow() => Flow::Propagate(res),
+            _ => Flow::Next,
+        }
+    }
+
+    /// Evaluates the condition of an `if`, a loop or a logical operator.
+    pub fn condition(&mut self, node: Box<Node>) -> Result<bool, String> {
+        match self.evaluate(node)? {
+            Some(value) => value.is_truthy(),
+            None => Err(String::from("Condition has no value.")),
+        }
+    }
+
     pub fn evaluate(&mut self, node: Box<Node>) -> Result<Option<Variable>, String> {
         match *node {
             Node::Call { name, args } => {
+                let shadowed = self.functions.as_ref().is_some_and(|functions| functions.contains_key(&name));
+                if BUILTIN_FUNCTIONS.contains(&name.as_str()) && !shadowed {
+                    return self.call_builtin(&name, &args);
+                }
+
                 let function_name = name;
-                let f = self.external_func.clone();
                 let mut args_value: Vec<Variable> = vec![];
                 for arg in args {
-                    if let Some(val) = self.evaluate(arg).expect("Cannot evaluate") {
-                        args_value.push(val);
-                    }
+                    args_value.push(self.evaluate(arg)?.unwrap_or(Variable::None {}));
                 }
 
                 if let Some(functions) = self.functions.clone() {
                     debug!("functions: {:?}", functions.iter().map(|f| format!("{},", f.0)).collect::<String>());
                     debug!("{}: {}", &function_name, functions.contains_key(&function_name));
                     if functions.contains_key(&function_name) {
-                        if let Node::Function { body, .. } = &*(functions[&function_name]) {
+                        if let Node::Function { args, return_type, body, .. } = &*(functions[&function_name]) {
+                            if args.len() != args_value.len() {
+                                return Err(format!(
+                                    "{}: expected {} arguments, found {}.",
+                                    function_name,
+                                    args.len(),
+                                    args_value.len()
+                                ));
+                            }
+
+                            let prompted = self.check_function_permission(&function_name, &functions[&function_name])?;
+
+                            let mut variables = HashMap::new();
+                            for ((name, var_type), value) in args.iter().zip(args_value) {
+                                let value = self.check_type(var_type, value)
+                                    .map_err(|err| format!("{}: argument `{}`: {}", function_name, name, err))?;
+                                variables.insert(
+                                    name.clone(),
+                                    LocalVariable {
+                                        name: name.clone(),
+                                        value,
+                                        var_type: Some(var_type.clone()),
+                                        status: VariableStatus { initialized: true },
+                                    },
+                                );
+                            }
+
+                            let mut block = self.blocks.front().unwrap().clone();
+                            block.accept.extend(prompted);
+                            self.blocks.push_front(Block {
+                                accept: block.accept,
+                                reject: block.reject,
+                                variables,
+                                is_split: true
+                            });
+
+                            self.call_stack.push(function_name.clone());
+
+                            let mut ret = Ok(None);
                             for program in body {
-                                let block = {
-                                    let blocks = self.blocks.clone();
-                                    blocks.front().unwrap().clone()
-                                };
-
-                                self.blocks.push_front(Block {
-                                    accept: block.accept.clone(),
-                                    reject: block.reject.clone(),
-                                    variables: HashMap::new(),
-                                    is_split: true
-                                });
-
-                                let res = self.evaluate(Box::new(*program.clone()));
-
-                                if let Ok(Some(res)) = res {
-                                    match res {
-                                        Variable::Return { value } => {
-                                            return Ok(Some(*value));
-                                        }
-                                        _ => {}
+                                match self.evaluate(program.clone()) {
+                                    Ok(Some(Variable::Return { value })) => {
+                                        ret = Ok(Some(*value));
+                                        break;
+                                    }
+                                    Ok(_) => {}
+                                    Err(err) => {
+                                        ret = Err(err);
+                                        break;
                                     }
-                                } else if let Err(err) = res {
-                                    return Err(err);
                                 }
-
-                                self.blocks.pop_front();
                             }
+
+                            self.blocks.pop_front();
+                            self.call_stack.pop();
+                            return self.check_return(&function_name, return_type, ret?);
                         }
-                        return Ok(None);
+                        return Err(format!("`{}` is not a function.", function_name));
                     }
                 }
 
-                debug!("Searching external: {}, ({:?})", &function_name, args_value);
-
-                for func in f {
-                    let block = self.blocks.front().unwrap();
-                    let res = func(function_name.clone(), args_value.clone(), block.accept.clone(), block.reject.clone());
-                    if res.status == ExternalFuncStatus::SUCCESS {
-                        return Ok(res.value);
-                    }
-                    if res.status == ExternalFuncStatus::REJECTED {
-                        return Err("External function rejected.".to_string());
-                    }
+                if function_name == "mint" {
+                    return match args_value.as_slice() {
+                        [Variable::Text { value }] => Ok(Some(self.mint_capability(value)?)),
+                        _ => Err(String::from("mint: expected a permission name.")),
+                    };
                 }
 
-                Err(format!("Function not found: {}", function_name))
+                self.call_external(function_name, args_value)
             }
             Node::Text { value } => {
                 Ok(Some(Variable::Text {
@@ -167,346 +919,305 @@ impl GPSL {
                     value
                 }))
             }
+            Node::Int { value } => {
+                Ok(Some(Variable::Int {
+                    value
+                }))
+            }
+            Node::Float { value } => {
+                Ok(Some(Variable::Float {
+                    value
+                }))
+            }
             Node::Operator { kind, lhs, rhs } => {
                 if kind == NodeKind::ASSIGN {
                     debug!("Assign: {:?}", self.blocks.front());
 
-                    let rhs = self.evaluate(rhs);
+                    let rhs = self.evaluate(rhs)?.ok_or_else(|| String::from("RHS Variable is null."))?;
+                    self.assign(&lhs, rhs)?;
 
-                    if let Ok(Some(rhs)) = rhs {
-                        match *(lhs.clone()) {
-                            Node::Lvar { value } => {
-                                self.get_local_var_mut(&value).unwrap().value = rhs;
-                                self.get_local_var_mut(&value).unwrap().status.initialized = true;
-                            }
-                            _ => {}
-                        }
+                    return Ok(None);
+                }
+                if kind == NodeKind::AND || kind == NodeKind::OR {
+                    let value = match kind {
+                        NodeKind::AND => self.condition(lhs)? && self.condition(rhs)?,
+                        _ => self.condition(lhs)? || self.condition(rhs)?,
+                    };
+                    return Ok(Some(Variable::Bool { value }));
+                }
+                let lhs = self.evaluate(lhs)?.ok_or_else(|| String::from("LHS Variable is null."))?;
+                let rhs = self.evaluate(rhs)?.ok_or_else(|| String::from("RHS Variable is null."))?;
+
+                match kind {
+                    NodeKind::ADD | NodeKind::SUB | NodeKind::MUL | NodeKind::DIV | NodeKind::MOD => {
+                        GPSL::arithmetic(&kind, lhs, rhs).map(Some)
+                    }
+                    NodeKind::BITAND | NodeKind::BITOR | NodeKind::BITXOR | NodeKind::SHL | NodeKind::SHR => {
+                        GPSL::bitwise(&kind, lhs, rhs).map(Some)
+                    }
+                    NodeKind::EQ | NodeKind::NE | NodeKind::LT | NodeKind::LE => {
+                        GPSL::compare(&kind, lhs, rhs).map(Some)
                     }
+                    _ => Ok(None)
+                }
+            }
+            Node::Bool { value } => {
+                Ok(Some(Variable::Bool {
+                    value
+                }))
+            }
+            Node::Interpolation { parts } => {
+                let mut value = String::new();
+                for part in parts {
+                    match self.evaluate(part)? {
+                        Some(part) => value += &part.to_string(),
+                        None => return Err(String::from("Interpolated expression has no value.")),
+                    }
+                }
+                Ok(Some(Variable::Text { value }))
+            }
+            Node::Not { lhs } => {
+                Ok(Some(Variable::Bool {
+                    value: !self.condition(lhs)?
+                }))
+            }
+            Node::BitNot { lhs } => {
+                match self.evaluate(lhs)?.ok_or_else(|| String::from("LHS Variable is null."))? {
+                    Variable::Number { value } => Ok(Some(Variable::Number { value: !value })),
+                    Variable::Int { value } => Ok(Some(Variable::Int { value: !value })),
+                    value => Err(GPSL::integer_error(&value)),
+                }
+            }
+            Node::List { items } => {
+                let mut value = vec![];
+                for item in items {
+                    value.push(self.evaluate(item)?.ok_or_else(|| String::from("List item has no value."))?);
+                }
+                Ok(Some(Variable::List { value }))
+            }
+            Node::Map { entries } => {
+                let mut value = BTreeMap::new();
+                for (key, item) in entries {
+                    let key = self.evaluate(key)?.ok_or_else(|| String::from("Map key has no value."))?;
+                    let item = self.evaluate(item)?.ok_or_else(|| String::from("Map entry has no value."))?;
+                    value.insert(MapKey::new(&key)?, item);
+                }
+                Ok(Some(Variable::Map { value }))
+            }
+            Node::StructLiteral { name, fields } => {
+                let declared = self
+                    .struct_fields(&name)
+                    .cloned()
+                    .ok_or_else(|| format!("Unknown struct: {}", name))?;
+                if let Some((field, _)) = fields.iter().find(|(field, _)| !declared.iter().any(|(other, _)| other == field)) {
+                    return Err(format!("`{}` has no field `{}`.", name, field));
+                }
 
-                    return Ok(None);
+                let mut values = vec![];
+                for (field, field_type) in declared {
+                    let node = fields
+                        .iter()
+                        .find(|(other, _)| *other == field)
+                        .map(|(_, node)| node.clone())
+                        .ok_or_else(|| format!("{}: missing field `{}`.", name, field))?;
+                    let value = self.evaluate(node)?.ok_or_else(|| format!("{}.{}: no value.", name, field))?;
+                    let value = self
+                        .check_type(&field_type, value)
+                        .map_err(|err| format!("{}.{}: {}", name, field, err))?;
+                    values.push((field, value));
                 }
-                let lhs = self.evaluate(lhs).expect("Cannot evaluate lhs.");
-                let rhs = self.evaluate(rhs).expect("Cannot evaluate rhs.");
-
-                if let Some(lhs) = lhs {
-                    if let Some(rhs) = rhs {
-                        match kind {
-                            NodeKind::ADD => {
-                                match GPSL::extract_number(lhs) {
-                                    Ok(lhs) => {
-                                        match GPSL::extract_number(rhs) {
-                                            Ok(rhs) => {
-                                                Ok(Some(Variable::Number {
-                                                    value: lhs + rhs
-                                                }))
-                                            }
-                                            Err(err) => { Err(err) }
-                                        }
-                                    }
-                                    Err(err) => { Err(err) }
-                                }
-                            },
-                            NodeKind::DIV => {
-                                match GPSL::extract_number(lhs) {
-                                    Ok(lhs) => {
-                                        match GPSL::extract_number(rhs) {
-                                            Ok(rhs) => {
-                                                Ok(Some(Variable::Number {
-                                                    value: lhs / rhs
-                                                }))
-                                            }
-                                            Err(err) => { Err(err) }
-                                        }
-                                    }
-                                    Err(err) => { Err(err) }
-                                }
-                            },
-                            NodeKind::MUL => {
-                                match GPSL::extract_number(lhs) {
-                                    Ok(lhs) => {
-                                        match GPSL::extract_number(rhs) {
-                                            Ok(rhs) => {
-                                                Ok(Some(Variable::Number {
-                                                    value: lhs * rhs
-                                                }))
-                                            }
-                                            Err(err) => { Err(err) }
-                                        }
-                                    }
-                                    Err(err) => { Err(err) }
-                                }
-                            },
-                            NodeKind::SUB => {
-                                match GPSL::extract_number(lhs) {
-                                    Ok(lhs) => {
-                                        match GPSL::extract_number(rhs) {
-                                            Ok(rhs) => {
-                                                Ok(Some(Variable::Number {
-                                                    value: lhs - rhs
-                                                }))
-                                            }
-                                            Err(err) => { Err(err) }
-                                        }
-                                    }
-                                    Err(err) => { Err(err) }
-                                }
-                            },
-
-                            NodeKind::EQ => {
-                                if lhs == rhs {
-                                    Ok(Some(Variable::Number {
-                                        value: 1
-                                    }))
-                                } else {
-                                    Ok(Some(Variable::Number {
-                                        value: 0
-                                    }))
-                                }
-                            },
-                            NodeKind::NE => {
-                                if lhs != rhs {
-                                    Ok(Some(Variable::Number {
-                                        value: 1
-                                    }))
-                                } else {
-                                    Ok(Some(Variable::Number {
-                                        value: 0
-                                    }))
-                                }
-                            },
-                            NodeKind::LT => {
-                                match GPSL::extract_number(lhs) {
-                                    Ok(lhs) => {
-                                        match GPSL::extract_number(rhs) {
-                                            Ok(rhs) => {
-                                                if lhs < rhs {
-                                                    Ok(Some(Variable::Number {
-                                                        value: 1
-                                                    }))
-                                                } else {
-                                                    Ok(Some(Variable::Number {
-                                                        value: 0
-                                                    }))
-                                                }
-                                            }
-                                            Err(err) => { Err(err) }
-                                        }
-                                    }
-                                    Err(err) => { Err(err) }
-                                }
-                            },
-                            NodeKind::LE => {
-                                match GPSL::extract_number(lhs) {
-                                    Ok(lhs) => {
-                                        match GPSL::extract_number(rhs) {
-                                            Ok(rhs) => {
-                                                if lhs <= rhs {
-                                                    Ok(Some(Variable::Number {
-                                                        value: 1
-                                                    }))
-                                                } else {
-                                                    Ok(Some(Variable::Number {
-                                                        value: 0
-                                                    }))
-                                                }
-                                            }
-                                            Err(err) => { Err(err) }
-                                        }
-                                    }
-                                    Err(err) => { Err(err) }
-                                }
-                            },
-                            _ => Ok(None)
-                        }
-                    } else {
-                        Err(String::from("RHS Variable is null."))
+                Ok(Some(Variable::Struct { name, fields: values }))
+            }
+            Node::EnumVariant { name, variant, args } => {
+                let field_types = self
+                    .enum_variants(&name)
+                    .ok_or_else(|| format!("Unknown enum: {}", name))?
+                    .iter()
+                    .find(|(other, _)| *other == variant)
+                    .map(|(_, field_types)| field_types.clone())
+                    .ok_or_else(|| format!("`{}` has no variant `{}`.", name, variant))?;
+                if field_types.len() != args.len() {
+                    return Err(format!(
+                        "{}::{}: expected {} values, found {}.",
+                        name,
+                        variant,
+                        field_types.len(),
+                        args.len()
+                    ));
+                }
+
+                let mut values = vec![];
+                for (arg, field_type) in args.into_iter().zip(&field_types) {
+                    let value = self.evaluate(arg)?.ok_or_else(|| format!("{}::{}: no value.", name, variant))?;
+                    values.push(
+                        self.check_type(field_type, value)
+                            .map_err(|err| format!("{}::{}: {}", name, variant, err))?,
+                    );
+                }
+                Ok(Some(Variable::Enum { name, variant, values }))
+            }
+            Node::Match { value, arms } => {
+                let value = self.evaluate(value)?.ok_or_else(|| String::from("Cannot match on none."))?;
+                for (pattern, stmt) in arms {
+                    let mut bindings = vec![];
+                    if !GPSL::pattern_matches(&pattern, &value, &mut bindings)? {
+                        continue;
                     }
-                } else {
-                    Err(String::from("LHS Variable is null."))
+
+                    let parent = self.blocks.front().unwrap().clone();
+                    let variables = bindings
+                        .into_iter()
+                        .map(|(name, value)| {
+                            let variable = LocalVariable {
+                                name: name.clone(),
+                                value,
+                                var_type: None,
+                                status: VariableStatus { initialized: true },
+                            };
+                            (name, variable)
+                        })
+                        .collect();
+                    self.blocks.push_front(Block {
+                        accept: parent.accept,
+                        reject: parent.reject,
+                        variables,
+                        is_split: false
+                    });
+                    let ret = self.evaluate(stmt);
+                    self.blocks.pop_front();
+
+                    return match ret? {
+                        Some(res) if res.is_control_flow() => Ok(Some(res)),
+                        _ => Ok(None),
+                    };
                 }
+
+                Err(format!("No match arm for value {}.", value))
+            }
+            Node::Field { target, name } => {
+                let target = self.evaluate(target)?.ok_or_else(|| format!("Cannot read field `{}` of none.", name))?;
+                target.field(&name).map(Some)
+            }
+            Node::Index { target, index } => {
+                let target = self.evaluate(target)?.ok_or_else(|| String::from("Cannot index into none."))?;
+                let index = self.evaluate(index)?.ok_or_else(|| String::from("Index has no value."))?;
+                target.index(&index).map(Some)
             }
             Node::Lvar { value } => {
-                return Ok(Some(self.get_local_var(&value).unwrap().value.clone()));
+                match self.get_local_var(&value) {
+                    Some(variable) => Ok(Some(variable.value)),
+                    None => Err(format!("Undefined variable: {}", value)),
+                }
             }
             Node::Return { lhs } => {
-                if let Ok(Some(lhs)) = self.evaluate(lhs) {
-                    return Ok(Some(Variable::Return {
-                        value: Box::new(lhs)
-                    }));
-                } else {
-                    return Err(String::from("Cannot evaluate LHS."));
-                }
+                let lhs = self.evaluate(lhs)?.ok_or_else(|| String::from("Cannot evaluate LHS."))?;
+                Ok(Some(Variable::Return {
+                    value: Box::new(lhs)
+                }))
             }
             Node::If {
                 condition,
                 stmt,
                 else_stmt,
             } => {
-                if let Ok(Some(condition)) = self.evaluate(condition) {
-                    if match condition {
-                        Variable::Number { value } => value == 1,
-                        _ => false
-                    } {
-                        if let Ok(Some(res)) = self.evaluate(stmt) {
-                            match res.clone() {
-                                Variable::Return { .. } => {
-                                    return Ok(Some(res));
-                                }
-                                _ => {}
-                            }
-                        }
-                    } else {
-                        match else_stmt {
-                            Some(else_stmt) => {
-                                if let Ok(Some(res)) = self.evaluate(else_stmt) {
-                                    match res.clone() {
-                                        Variable::Return { .. } => {
-                                            return Ok(Some(res));
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                            }
-                            None => {}
-                        }
+                let branch = if self.condition(condition)? {
+                    Some(stmt)
+                } else {
+                    else_stmt
+                };
+
+                if let Some(branch) = branch {
+                    match self.evaluate(branch)? {
+                        Some(res) if res.is_control_flow() => return Ok(Some(res)),
+                        _ => {}
                     }
                 }
 
-                return Ok(None);
+                Ok(None)
             }
-            Node::While { condition, stmt } => {
-                let mut cond = if let Some(condition) = self.evaluate(condition.clone())? {
-                    condition
-                } else {
-                    Variable::Number {
-                        value: 0
+            Node::While { label, condition, stmt } => {
+                while self.condition(condition.clone())? {
+                    match GPSL::loop_flow(&label, self.evaluate(stmt.clone())?) {
+                        Flow::Next => {}
+                        Flow::Exit => break,
+                        Flow::Propagate(res) => return Ok(Some(res)),
                     }
-                };
-
-                while match cond {
-                    Variable::Number { value } => value == 1,
-                    _ => false
-                } {
-                    self.evaluate(stmt.clone())?;
-                    cond = if let Some(condition) = self.evaluate(condition.clone())? {
-                        condition
-                    } else {
-                        Variable::Number {
-                            value: 0
-                        }
-                    };
                 }
 
                 return Ok(None);
             }
             Node::For {
+                label,
                 init,
                 condition,
                 update,
                 stmt,
             } => {
-                match init {
-                    Some(init) => {self.evaluate(init)?;},
-                    None => {}
-                }
-
-                let mut cond = match condition.clone() {
-                    Some(condition) => {
-                        if let Some(condition) = self.evaluate(condition)? {
-                            condition
-                        } else {
-                            Variable::Number {
-                                value: 0
-                            }
-                        }
-                    },
-                    None => {
-                        Variable::Number {
-                            value: 1
-                        }
-                    }
-                };
+                if let Some(init) = init {
+                    self.evaluate(init)?;
+                }
 
-                while match cond {
-                    Variable::Number { value } => value == 1,
-                    _ => false
+                while match condition.clone() {
+                    Some(condition) => self.condition(condition)?,
+                    None => true,
                 } {
-                    self.evaluate(stmt.clone())?;
-
-                    match update.clone() {
-                        Some(update) => {self.evaluate(update)?;},
-                        None => {}
+                    match GPSL::loop_flow(&label, self.evaluate(stmt.clone())?) {
+                        Flow::Next => {}
+                        Flow::Exit => break,
+                        Flow::Propagate(res) => return Ok(Some(res)),
                     }
 
-                    cond = match condition.clone() {
-                        Some(condition) => {
-                            if let Some(condition) = self.evaluate(condition)? {
-                                condition
-                            } else {
-                                Variable::Number {
-                                    value: 0
-                                }
-                            }
-                        },
-                        None => {
-                            Variable::Number {
-                                value: 1
-                            }
-                        }
-                    };
+                    if let Some(update) = update.clone() {
+                        self.evaluate(update)?;
+                    }
                 }
 
                 return Ok(None);
             }
+            Node::Break { label } => Ok(Some(Variable::Break { label })),
+            Node::Continue { label } => Ok(Some(Variable::Continue { label })),
             Node::Block { stmts, permission } => {
-                let accept = self.blocks.front().unwrap().accept.clone();
-                let reject = self.blocks.front().unwrap().reject.clone();
+                let parent = self.blocks.front().unwrap().clone();
                 let (accept, reject) = if let Node::Permission { accept, reject } = *permission.unwrap_or(Box::new(Node::None)) {
-                    (accept.iter().map(|p| Permission::from_string(p)).collect(), reject.iter().map(|p| Permission::from_string(p)).collect())
+                    self.narrow_permissions(&parent, &accept, &reject)?
                 } else {
-                    (accept, reject)
+                    (parent.accept, parent.reject)
                 };
 
                 self.blocks.push_front(Block {
-                    accept: accept,
-                    reject: reject,
+                    accept,
+                    reject,
                     variables: HashMap::new(),
                     is_split: false
                 });
 
+                let mut ret = Ok(None);
                 for stmt in stmts {
-                    let ret = self.evaluate(stmt)?;
-                    if let Some(ret) = ret {
-                        match ret.clone() {
-                            Variable::Return { .. } => {
-                                return Ok(Some(ret));
-                            }
-                            _ => {}
+                    match self.evaluate(stmt) {
+                        Ok(Some(res)) if res.is_control_flow() => {
+                            ret = Ok(Some(res));
+                            break;
+                        }
+                        Ok(_) => {}
+                        Err(err) => {
+                            ret = Err(err);
+                            break;
                         }
                     }
                 }
 
                 self.blocks.pop_front();
 
-                return Ok(None);
+                ret
             }
             Node::Define { name, var_type } => {
-                let value = if var_type == "num" {
-                    Variable::Number {
-                        value: 0
-                    }
-                } else if var_type == "String" {
-                    Variable::Text {
-                        value: String::default()
-                    }
-                } else {
-                    return Err(format!("{}: 未知の型です。", var_type));
-                };
+                let value = self.default_value(&var_type)?;
                 self.blocks.front_mut().unwrap().variables.insert(
                     name.clone(),
                     LocalVariable {
                         name,
                         value,
+                        var_type: Some(var_type),
                         status: VariableStatus::default(),
                     },
                 );
@@ -522,30 +1233,293 @@ impl GPSL {
     pub fn run(&mut self, function_name: String, _: Vec<Box<Node>>) -> Result<Variable, String> {
         debug!("functions: {:?}", self.functions);
         debug!("searching {}", function_name);
+        self.blocks.clear();
+        self.call_stack = vec![function_name.clone()];
         self.blocks.push_front(Block {
-            accept: vec![Permission::Administrator, Permission::StdIo],
-            reject: vec![],
+            accept: self.root_accept.clone(),
+            reject: self.root_reject.clone(),
             variables: HashMap::new(),
             is_split: true
         });
         if let Some(functions) = self.functions.clone() {
-            if let Node::Function { body, .. } = &*(functions[&function_name]) {
+            let function = functions
+                .get(&function_name)
+                .ok_or_else(|| format!("Function not found: {}", function_name))?;
+            if let Node::Function { return_type, body, .. } = &**function {
+                let prompted = self.check_function_permission(&function_name, function)?;
+                self.blocks.front_mut().unwrap().accept.extend(prompted);
+
+                let mut ret = None;
                 for program in body {
-                    let res = self.evaluate(Box::new(*program.clone()));
-                    if let Ok(Some(res)) = res {
-                        match res {
-                            Variable::Return { value } => {
-                                return Ok(*value);
-                            }
-                            _ => {}
-                        }
-                    } else if let Err(err) = res {
-                        return Err(err);
+                    if let Some(Variable::Return { value }) = self.evaluate(program.clone())? {
+                        ret = Some(*value);
+                        break;
                     }
                 }
+
+                return Ok(self.check_return(&function_name, return_type, ret)?.unwrap_or(Variable::None {}));
             }
+            return Err(format!("`{}` is not a function.", function_name));
         }
 
         Ok(Variable::None {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_function::STD_FUNC;
+    use crate::parser::Parser;
+    use crate::tokenizer::Tokenizer;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    fn block(accept: Vec<Permission>, reject: Vec<Permission>) -> Block {
+        Block {
+            accept,
+            reject,
+            variables: HashMap::new(),
+            is_split: false,
+        }
+    }
+
+    fn vm(src: &str) -> GPSL {
+        let mut source = Source::new(src.to_string());
+        let mut tokenizer = Tokenizer::new();
+        tokenizer.tokenize(&mut source).unwrap();
+        let mut parser = Parser {
+            tokenizer,
+            local_vars: HashMap::new(),
+        };
+        let functions = parser.functions().unwrap();
+        GPSL::new(source, Some(functions), vec![STD_FUNC])
+    }
+
+    #[test]
+    fn rejected_permissions_stay_rejected_in_nested_blocks() {
+        let mut gpsl = GPSL::new(Source::new(String::new()), None, vec![]);
+        gpsl.prompt = Some(Box::new(|_, _| PromptDecision::AllowAlways));
+        let root = block(vec![Permission::administrator(), Permission::std_io()], vec![]);
+
+        let (accept, reject) = gpsl.narrow_permissions(&root, &[], &[Permission::std_io()]).unwrap();
+        let outer = block(accept, reject);
+        assert!(!outer.holds(&Permission::std_io()));
+
+        let (accept, reject) = gpsl.narrow_permissions(&outer, &[], &[]).unwrap();
+        assert!(!block(accept, reject).holds(&Permission::std_io()));
+
+        assert!(gpsl.narrow_permissions(&outer, &[Permission::std_io()], &[]).is_err());
+        assert!(gpsl.always_allowed.is_empty());
+    }
+
+    #[test]
+    fn nested_block_cannot_widen_permissions() {
+        let mut gpsl = GPSL::new(Source::new(String::new()), None, vec![]);
+        let parent = block(vec![Permission::std_io()], vec![]);
+        assert!(gpsl.narrow_permissions(&parent, &[Permission::administrator()], &[]).is_err());
+
+        let (accept, _) = gpsl.narrow_permissions(&parent, &[Permission::std_io()], &[]).unwrap();
+        assert_eq!(accept, vec![Permission::std_io()]);
+    }
+
+    fn failing_func(name: String, _: Vec<Variable>, _: Vec<Permission>, _: Vec<Permission>) -> ExternalFuncReturn {
+        ExternalFuncReturn {
+            status: if name == "fail" { ExternalFuncStatus::ERROR } else { ExternalFuncStatus::NOTFOUND },
+            value: Some(Variable::Text { value: String::from("fail: broken.") }),
+        }
+    }
+
+    fn read_func(name: String, _: Vec<Variable>, _: Vec<Permission>, _: Vec<Permission>) -> ExternalFuncReturn {
+        ExternalFuncReturn {
+            status: if name == "read" { ExternalFuncStatus::SUCCESS } else { ExternalFuncStatus::NOTFOUND },
+            value: None,
+        }
+    }
+
+    fn record_events(gpsl: &mut GPSL) -> Rc<RefCell<Vec<PermissionEvent>>> {
+        let events = Rc::new(RefCell::new(vec![]));
+        let sink = events.clone();
+        gpsl.audit_sink = Some(Box::new(move |event| sink.borrow_mut().push(event.clone())));
+        events
+    }
+
+    #[test]
+    fn audit_sink_records_decisions_with_caller_chain() {
+        let mut gpsl = vm("fn log() { print(\"\"); } fn main() { log(); $(reject[StdIo]) { print(\"\"); } }");
+        let events = record_events(&mut gpsl);
+        assert!(gpsl.run(String::from("main"), vec![]).is_err());
+
+        let events: Vec<_> = events
+            .borrow()
+            .iter()
+            .map(|event| (event.permission.clone(), event.function.clone(), event.caller_chain.clone(), event.decision))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (
+                    Some(Permission::std_io()),
+                    String::from("print"),
+                    vec![String::from("main"), String::from("log")],
+                    PermissionDecision::Granted
+                ),
+                (
+                    Some(Permission::std_io()),
+                    String::from("print"),
+                    vec![String::from("main")],
+                    PermissionDecision::Rejected
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn audit_sink_records_failed_external_calls() {
+        let mut gpsl = vm("fn main() { fail(); }");
+        gpsl.external_func = vec![failing_func];
+        gpsl.external_permissions.insert(String::from("fail"), vec![Permission::std_io()]);
+        let events = record_events(&mut gpsl);
+        assert_eq!(gpsl.run(String::from("main"), vec![]), Err(String::from("fail: broken.")));
+
+        let events = events.borrow();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].function, "fail");
+        assert_eq!(events[0].decision, PermissionDecision::Granted);
+    }
+
+    #[test]
+    fn quota_on_parent_counts_child_permissions() {
+        let fs = Permission::new("Fs");
+        let mut gpsl = vm("fn main() { read(); read(); read(); }").with_permissions(vec![fs.clone()], vec![]);
+        gpsl.external_func = vec![read_func];
+        gpsl.external_permissions.insert(String::from("read"), vec![Permission::new("Fs.Read")]);
+        gpsl.quotas.insert(fs.clone(), 2);
+        assert_eq!(
+            gpsl.run(String::from("main"), vec![]),
+            Err(String::from("read: quota for permission Fs exhausted."))
+        );
+        assert_eq!(gpsl.quotas[&fs], 0);
+    }
+
+    #[test]
+    fn manifest_caps_root_permissions() {
+        let mut gpsl = vm("fn main() { print(\"\"); }");
+        gpsl.apply_manifest(&Manifest::parse("accept = [\"StdIo\"]").unwrap()).unwrap();
+        assert_eq!(gpsl.root_accept, vec![Permission::std_io()]);
+        assert!(gpsl.run(String::from("main"), vec![]).is_ok());
+
+        let mut gpsl = vm("fn main() { $(accept[Administrator]) { print(\"\"); } }");
+        assert_eq!(
+            gpsl.apply_manifest(&Manifest::parse("accept = [\"StdIo\"]").unwrap()),
+            Err(String::from("main: Administrator is not granted by the manifest."))
+        );
+    }
+
+    #[test]
+    fn prompt_cannot_grant_beyond_manifest() {
+        let mut gpsl = vm("fn main() { print(\"\"); }");
+        gpsl.apply_manifest(&Manifest::parse("accept = []").unwrap()).unwrap();
+        gpsl.prompt = Some(Box::new(|_, _| PromptDecision::AllowAlways));
+        assert_eq!(
+            gpsl.run(String::from("main"), vec![]),
+            Err(String::from("print: permission StdIo rejected."))
+        );
+        assert!(gpsl.always_allowed.is_empty());
+    }
+
+    #[test]
+    fn policy_rules_follow_the_call_stack() {
+        let src = "fn logger() { print(\"\"); } fn main() { logger(); print(\"\"); }";
+
+        let mut gpsl = vm(src);
+        gpsl.policy = Policy::parse("deny StdIo in log*").unwrap();
+        assert_eq!(
+            gpsl.run(String::from("main"), vec![]),
+            Err(String::from("print: permission StdIo denied by policy rule `deny StdIo in log*`."))
+        );
+
+        let mut gpsl = vm(src);
+        gpsl.policy = Policy::parse("allow StdIo only in log*").unwrap();
+        assert_eq!(
+            gpsl.run(String::from("main"), vec![]),
+            Err(String::from("print: permission StdIo denied by policy rule `allow StdIo only in log*`."))
+        );
+    }
+
+    #[test]
+    fn capability_mode_grants_only_passed_capabilities() {
+        let mut gpsl = vm("fn main() { let c: cap; c = mint(\"StdIo\"); print(c, \"\"); }");
+        gpsl.capability_mode = true;
+        assert!(gpsl.run(String::from("main"), vec![]).is_ok());
+
+        let mut gpsl = vm("fn main() { print(\"\"); }");
+        gpsl.capability_mode = true;
+        gpsl.prompt = Some(Box::new(|_, _| PromptDecision::AllowOnce));
+        assert_eq!(
+            gpsl.run(String::from("main"), vec![]),
+            Err(String::from("print: permission StdIo rejected."))
+        );
+    }
+
+    #[test]
+    fn capabilities_need_held_permission_and_vm_origin() {
+        let mut gpsl = vm("fn main() { mint(\"Administrator\"); }").with_permissions(vec![Permission::std_io()], vec![]);
+        assert_eq!(
+            gpsl.run(String::from("main"), vec![]),
+            Err(String::from("mint: missing permission Administrator."))
+        );
+
+        let forged = Variable::Capability {
+            permission: Permission::administrator(),
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(
+            gpsl.capability_permissions(&[forged]),
+            Err(String::from("Invalid capability for Administrator."))
+        );
+    }
+
+    #[test]
+    fn prompt_remembers_allow_always() {
+        let asked = Rc::new(Cell::new(0));
+        let mut gpsl = vm("fn main() { print(\"\"); print(\"\"); }").with_permissions(vec![], vec![]);
+        let counter = asked.clone();
+        gpsl.prompt = Some(Box::new(move |permission, function_name| {
+            assert_eq!((permission, function_name), (&Permission::std_io(), "print"));
+            counter.set(counter.get() + 1);
+            PromptDecision::AllowAlways
+        }));
+        assert!(gpsl.run(String::from("main"), vec![]).is_ok());
+        assert_eq!(asked.get(), 1);
+        assert_eq!(gpsl.always_allowed, vec![Permission::std_io()]);
+
+        let mut gpsl = vm("fn main() { print(\"\"); }").with_permissions(vec![], vec![]);
+        gpsl.prompt = Some(Box::new(|_, _| PromptDecision::Deny));
+        assert_eq!(
+            gpsl.run(String::from("main"), vec![]),
+            Err(String::from("print: permission StdIo rejected."))
+        );
+    }
+
+    #[test]
+    fn prompted_permissions_count_against_quotas() {
+        let mut gpsl = vm("fn main() { print(\"\"); print(\"\"); }").with_permissions(vec![], vec![]);
+        gpsl.quotas.insert(Permission::std_io(), 1);
+        gpsl.prompt = Some(Box::new(|_, _| PromptDecision::AllowOnce));
+        assert_eq!(
+            gpsl.run(String::from("main"), vec![]),
+            Err(String::from("print: quota for permission StdIo exhausted."))
+        );
+        assert_eq!(gpsl.quotas[&Permission::std_io()], 0);
+    }
+
+    #[test]
+    fn script_cannot_regain_rejected_permission() {
+        let mut gpsl = vm("fn main() { $(reject[StdIo]) { $(accept[Administrator]) { println(\"x\"); } } }");
+        assert_eq!(
+            gpsl.run(String::from("main"), vec![]),
+            Err(String::from("println: permission StdIo rejected."))
+        );
+    }
+}